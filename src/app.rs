@@ -1,7 +1,9 @@
 mod calculate;
 mod gif_recorder;
 mod gui;
+mod jobs;
 mod morph_sim;
+mod overlay;
 mod preset;
 
 #[cfg(target_arch = "wasm32")]
@@ -61,13 +63,29 @@ const DEFAULT_RESOLUTION: u32 = 2048;
 #[cfg(target_arch = "wasm32")]
 const DEFAULT_RESOLUTION: u32 = 1024;
 
+/// Size of a canvas `longest` pixels along its longer side, shaped like a grid of `grid` cells.
+fn canvas_size((w, h): (u32, u32), longest: u32) -> (u32, u32) {
+    let (w, h, l) = (w.max(1) as u64, h.max(1) as u64, longest as u64);
+    if w >= h {
+        (longest, ((l * h + w / 2) / w).max(1) as u32)
+    } else {
+        (((l * w + h / 2) / h).max(1) as u32, longest)
+    }
+}
+
+/// Width of the seed position and colour lookup textures, seed `i` is at `(i % width, i / width)`.
+/// The shaders use the same width. A 1024² grid fills 1024 rows, within WebGL's 2048 limit.
+const LOOKUP_TEX_WIDTH: u32 = 1024;
+
 pub enum GuiMode {
     Transform,
     #[cfg(not(target_arch = "wasm32"))]
     Draw,
 }
 
-use crate::app::{calculate::ProgressMsg, morph_sim::Sim, preset::UnprocessedPreset};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::app::calculate::ProgressMsg;
+use crate::app::{calculate::JobMsg, morph_sim::Sim, preset::UnprocessedPreset};
 use crate::app::{calculate::util::GenerationSettings, preset::Preset};
 
 #[cfg(target_arch = "wasm32")]
@@ -84,15 +102,15 @@ pub struct VantaMorphApp {
     seed_count: u32,
 
     #[cfg(not(target_arch = "wasm32"))]
-    progress_tx: mpsc::SyncSender<ProgressMsg>,
+    progress_tx: mpsc::SyncSender<JobMsg>,
     #[cfg(not(target_arch = "wasm32"))]
-    progress_rx: mpsc::Receiver<ProgressMsg>,
+    progress_rx: mpsc::Receiver<JobMsg>,
 
     #[cfg(target_arch = "wasm32")]
     worker: Option<Worker>,
 
     #[cfg(target_arch = "wasm32")]
-    inbox: Vec<ProgressMsg>,
+    inbox: Vec<JobMsg>,
 
     gif_recorder: gif_recorder::GifRecorder,
     sim: Sim,
@@ -249,7 +267,8 @@ impl VantaMorphApp {
         // Drop any pending preview so the new preset's simulation renders immediately
         self.preview_image = None;
 
-        let (seed_count, mut seeds, colors, mut sim) = morph_sim::init_image(self.size.0, source);
+        self.fit_canvas(device, (source.inner.width, source.inner.height));
+        let (seed_count, mut seeds, colors, mut sim) = morph_sim::init_image(self.size, source);
         sim.prepare_play(&mut seeds, self.reverse);
         self.apply_sim_init(device, queue, seed_count, seeds, colors, sim);
         self.gui.current_preset = change_index;
@@ -262,10 +281,24 @@ impl VantaMorphApp {
         queue: &wgpu::Queue,
         source: &UnprocessedPreset,
     ) {
-        let (seed_count, seeds, colors, sim) = morph_sim::init_canvas(self.size.0, source.clone());
+        self.fit_canvas(device, (source.width, source.height));
+        let (seed_count, seeds, colors, sim) = morph_sim::init_canvas(self.size, source.clone());
         self.apply_sim_init(device, queue, seed_count, seeds, colors, sim);
     }
 
+    /// Reshapes the canvas like a grid of `grid` cells, keeping its longer side.
+    fn fit_canvas(&mut self, device: &wgpu::Device, grid: (u32, u32)) {
+        let size = canvas_size(grid, self.size.0.max(self.size.1));
+        if size != self.size {
+            self.resize_textures(device, size, false);
+        }
+    }
+
+    /// A canvas `longest` pixels along its longer side, shaped like the shown preset's grid.
+    fn sim_canvas(&self, longest: u32) -> (u32, u32) {
+        canvas_size(self.sim.grid(), longest)
+    }
+
     pub fn new(cc: &CreationContext<'_>) -> Self {
         let rs = cc
             .wgpu_render_state
@@ -273,7 +306,6 @@ impl VantaMorphApp {
             .expect("eframe must be built with the 'wgpu' feature and Renderer::Wgpu")
             .clone();
         let device = &rs.device;
-        let size = (DEFAULT_RESOLUTION, DEFAULT_RESOLUTION);
         egui_extras::install_image_loaders(&cc.egui_ctx);
 
         // get all folders in ../presets
@@ -294,8 +326,10 @@ impl VantaMorphApp {
             false
         };
 
+        let preset = &presets[random_preset].inner;
+        let size = canvas_size((preset.width, preset.height), DEFAULT_RESOLUTION);
         let (seed_count, seeds, colors, sim) =
-            morph_sim::init_image(size.0, presets[random_preset].clone());
+            morph_sim::init_image(size, presets[random_preset].clone());
 
         // === Buffers ===
         let seed_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -758,7 +792,7 @@ impl VantaMorphApp {
         });
 
         #[cfg(not(target_arch = "wasm32"))]
-        let (progress_tx, progress_rx) = mpsc::sync_channel::<ProgressMsg>(1);
+        let (progress_tx, progress_rx) = mpsc::sync_channel::<JobMsg>(1);
 
         Self {
             size,
@@ -824,7 +858,7 @@ impl VantaMorphApp {
         }
     }
 
-    pub fn get_latest_msg(&mut self) -> Option<ProgressMsg> {
+    pub fn get_latest_msg(&mut self) -> Option<JobMsg> {
         #[cfg(target_arch = "wasm32")]
         {
             self.inbox.pop()
@@ -927,9 +961,9 @@ impl VantaMorphApp {
 
         // Receive progress messages
         {
-            let inbox_ptr: *mut Vec<ProgressMsg> = &mut self.inbox;
+            let inbox_ptr: *mut Vec<JobMsg> = &mut self.inbox;
             let onmessage = Closure::wrap(Box::new(move |e: web_sys::MessageEvent| {
-                if let Ok(msg) = serde_wasm_bindgen::from_value::<ProgressMsg>(e.data()) {
+                if let Ok(msg) = serde_wasm_bindgen::from_value::<JobMsg>(e.data()) {
                     // SAFETY: single-threaded; worker posts to main thread
                     unsafe {
                         (*inbox_ptr).push(msg);
@@ -944,12 +978,22 @@ impl VantaMorphApp {
     }
 
     #[cfg(target_arch = "wasm32")]
-    fn start_job(&mut self, src: UnprocessedPreset, settings: GenerationSettings) {
+    fn start_job(
+        &mut self,
+        src: UnprocessedPreset,
+        settings: GenerationSettings,
+        control: &calculate::util::JobControl,
+    ) {
+        self.send_to_worker(calculate::worker::WorkerReq::Process {
+            source: src,
+            settings,
+            control: control.shared(),
+        });
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn send_to_worker(&self, req: calculate::worker::WorkerReq) {
         if let Some(w) = &self.worker {
-            let req = calculate::worker::WorkerReq::Process {
-                source: src,
-                settings,
-            };
             let v = serde_wasm_bindgen::to_value(&req).unwrap();
             w.post_message(&v).unwrap();
         }
@@ -1021,14 +1065,12 @@ impl VantaMorphApp {
         max_seeds: u32,
     ) -> (wgpu::Texture, wgpu::TextureView) {
         // Pack seeds into a 2D texture to respect WebGL texture size limits (typically 2048-4096)
-        // Use a square-ish layout: width = 1024, height = ceil(max_seeds / 1024)
-        const TEX_WIDTH: u32 = 1024;
-        let tex_height = max_seeds.div_ceil(TEX_WIDTH);
+        let tex_height = max_seeds.div_ceil(LOOKUP_TEX_WIDTH);
 
         let tex = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("seed_positions"),
             size: wgpu::Extent3d {
-                width: TEX_WIDTH,
+                width: LOOKUP_TEX_WIDTH,
                 height: tex_height,
                 depth_or_array_layers: 1,
             },
@@ -1041,7 +1083,7 @@ impl VantaMorphApp {
         });
 
         // Upload seed data to texture (packed in 2D)
-        let mut data = vec![0.0f32; (TEX_WIDTH * tex_height * 2) as usize];
+        let mut data = vec![0.0f32; (LOOKUP_TEX_WIDTH * tex_height * 2) as usize];
         for (i, seed) in seeds.iter().enumerate() {
             data[i * 2] = seed.xy[0];
             data[i * 2 + 1] = seed.xy[1];
@@ -1057,11 +1099,11 @@ impl VantaMorphApp {
             bytemuck::cast_slice(&data),
             wgpu::TexelCopyBufferLayout {
                 offset: 0,
-                bytes_per_row: Some(TEX_WIDTH * 8), // 2 floats * 4 bytes per pixel
+                bytes_per_row: Some(LOOKUP_TEX_WIDTH * 8), // 2 floats * 4 bytes per pixel
                 rows_per_image: Some(tex_height),
             },
             wgpu::Extent3d {
-                width: TEX_WIDTH,
+                width: LOOKUP_TEX_WIDTH,
                 height: tex_height,
                 depth_or_array_layers: 1,
             },
@@ -1073,10 +1115,9 @@ impl VantaMorphApp {
 
     fn update_seed_texture_data(&self, queue: &wgpu::Queue, seeds: &[SeedPos]) {
         // Update seed texture data without recreating the texture
-        const TEX_WIDTH: u32 = 1024;
-        let tex_height = self.seed_count.div_ceil(TEX_WIDTH);
+        let tex_height = self.seed_count.div_ceil(LOOKUP_TEX_WIDTH);
 
-        let mut data = vec![0.0f32; (TEX_WIDTH * tex_height * 2) as usize];
+        let mut data = vec![0.0f32; (LOOKUP_TEX_WIDTH * tex_height * 2) as usize];
         for (i, seed) in seeds.iter().enumerate() {
             data[i * 2] = seed.xy[0];
             data[i * 2 + 1] = seed.xy[1];
@@ -1092,11 +1133,11 @@ impl VantaMorphApp {
             bytemuck::cast_slice(&data),
             wgpu::TexelCopyBufferLayout {
                 offset: 0,
-                bytes_per_row: Some(TEX_WIDTH * 8), // 2 floats * 4 bytes per pixel
+                bytes_per_row: Some(LOOKUP_TEX_WIDTH * 8), // 2 floats * 4 bytes per pixel
                 rows_per_image: Some(tex_height),
             },
             wgpu::Extent3d {
-                width: TEX_WIDTH,
+                width: LOOKUP_TEX_WIDTH,
                 height: tex_height,
                 depth_or_array_layers: 1,
             },
@@ -1110,13 +1151,12 @@ impl VantaMorphApp {
         max_seeds: u32,
     ) -> (wgpu::Texture, wgpu::TextureView) {
         // Pack colors into a 2D texture to respect WebGL texture size limits
-        const TEX_WIDTH: u32 = 1024;
-        let tex_height = max_seeds.div_ceil(TEX_WIDTH);
+        let tex_height = max_seeds.div_ceil(LOOKUP_TEX_WIDTH);
 
         let tex = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("color_lookup"),
             size: wgpu::Extent3d {
-                width: TEX_WIDTH,
+                width: LOOKUP_TEX_WIDTH,
                 height: tex_height,
                 depth_or_array_layers: 1,
             },
@@ -1129,7 +1169,7 @@ impl VantaMorphApp {
         });
 
         // Upload color data to texture (packed in 2D)
-        let mut data = vec![0.0f32; (TEX_WIDTH * tex_height * 4) as usize];
+        let mut data = vec![0.0f32; (LOOKUP_TEX_WIDTH * tex_height * 4) as usize];
         for (i, color) in colors.iter().enumerate() {
             data[i * 4] = color.rgba[0];
             data[i * 4 + 1] = color.rgba[1];
@@ -1147,11 +1187,11 @@ impl VantaMorphApp {
             bytemuck::cast_slice(&data),
             wgpu::TexelCopyBufferLayout {
                 offset: 0,
-                bytes_per_row: Some(TEX_WIDTH * 16), // 4 floats * 4 bytes per pixel
+                bytes_per_row: Some(LOOKUP_TEX_WIDTH * 16), // 4 floats * 4 bytes per pixel
                 rows_per_image: Some(tex_height),
             },
             wgpu::Extent3d {
-                width: TEX_WIDTH,
+                width: LOOKUP_TEX_WIDTH,
                 height: tex_height,
                 depth_or_array_layers: 1,
             },
@@ -1560,7 +1600,7 @@ impl VantaMorphApp {
     fn stop_recording_gif(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
         self.gif_recorder.stop();
         self.gui.animate = false;
-        self.resize_textures(device, (DEFAULT_RESOLUTION, DEFAULT_RESOLUTION), false);
+        self.resize_textures(device, self.sim_canvas(DEFAULT_RESOLUTION), false);
         self.reset_sim(device, queue);
     }
 
@@ -1634,11 +1674,10 @@ impl VantaMorphApp {
         }
 
         // Update the color lookup texture with modified colors
-        const TEX_WIDTH: u32 = 1024;
-        let tex_height = self.seed_count.div_ceil(TEX_WIDTH);
+        let tex_height = self.seed_count.div_ceil(LOOKUP_TEX_WIDTH);
 
         let colors = self.colors.read().unwrap();
-        let mut data = vec![0.0f32; (TEX_WIDTH * tex_height * 4) as usize];
+        let mut data = vec![0.0f32; (LOOKUP_TEX_WIDTH * tex_height * 4) as usize];
         for (i, color) in colors.iter().enumerate() {
             data[i * 4] = color.rgba[0];
             data[i * 4 + 1] = color.rgba[1];
@@ -1656,11 +1695,11 @@ impl VantaMorphApp {
             bytemuck::cast_slice(&data),
             wgpu::TexelCopyBufferLayout {
                 offset: 0,
-                bytes_per_row: Some(TEX_WIDTH * 16), // 4 floats * 4 bytes per pixel
+                bytes_per_row: Some(LOOKUP_TEX_WIDTH * 16), // 4 floats * 4 bytes per pixel
                 rows_per_image: Some(tex_height),
             },
             wgpu::Extent3d {
-                width: TEX_WIDTH,
+                width: LOOKUP_TEX_WIDTH,
                 height: tex_height,
                 depth_or_array_layers: 1,
             },
@@ -1669,7 +1708,7 @@ impl VantaMorphApp {
         // Keep the buffer for backward compatibility if needed elsewhere
         self.color_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("colors"),
-            contents: bytemuck::cast_slice(&*colors),
+            contents: bytemuck::cast_slice(&colors),
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
     }
@@ -1742,6 +1781,7 @@ impl VantaMorphApp {
             let current_id = self.current_drawing_id.clone();
             let my_id = current_id.load(std::sync::atomic::Ordering::SeqCst);
             let source = source.clone();
+            let id = settings.id;
             move || {
                 let result = calculate::drawing_process::drawing_process_genetic(
                     source,
@@ -1756,7 +1796,13 @@ impl VantaMorphApp {
                 match result {
                     Ok(()) => {}
                     Err(err) => {
-                        tx.send(ProgressMsg::Error(err.to_string())).ok();
+                        tx.send(JobMsg {
+                            id,
+                            msg: ProgressMsg::Error(calculate::error::CalculateError::Internal(
+                                err.to_string(),
+                            )),
+                        })
+                        .ok();
                     }
                 }
             }
@@ -1817,6 +1863,7 @@ macro_rules! include_presets {
                     };
 
                     Preset {
+                        id: uuid::Uuid::new_v4(),
                         inner: UnprocessedPreset {
                             name: $name.to_owned(),
                             width: img.width(),
@@ -1833,6 +1880,9 @@ macro_rules! include_presets {
                             .split(',')
                             .map(|s| s.parse().unwrap())
                             .collect::<Vec<usize>>(),
+                        metrics: None,
+                        mask: None,
+                        settings: None,
                     }
                 }),*
             ]
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+
+use crate::app::calculate::cost::Features;
+use crate::app::calculate::error::CalculateError;
+use crate::app::calculate::util::{Budget, GenerationSettings, ProgressSink};
+use crate::app::calculate::{
+    Pixel, ProgressMsg, SWAPS_PER_GENERATION_PER_PIXEL, Swaps, make_new_img, pixel_assignments,
+};
+
+// restarts and warm starts begin from a good mapping, so they only need local swaps
+const REHEAT_DIST: u32 = 16;
+// geometric cooling ends at this fraction of the starting temperature
+const GEOMETRIC_END: f32 = 1e-3;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Cooling {
+    /// Temperature shrinks by the same factor every generation.
+    Geometric,
+    /// Temperature drops by the same amount every generation, down to zero.
+    Linear,
+}
+
+impl Cooling {
+    /// Temperature multiplier after `fraction` of a run.
+    fn factor(&self, fraction: f32) -> f32 {
+        match self {
+            Cooling::Geometric => GEOMETRIC_END.powf(fraction),
+            Cooling::Linear => 1.0 - fraction,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Annealing {
+    /// Starting temperature, as a fraction of the mean cell cost.
+    pub temperature: f32,
+    pub cooling: Cooling,
+    /// Generations in each run.
+    pub generations: u32,
+    /// Extra runs, each reheated from the best mapping found so far.
+    pub restarts: u32,
+}
+
+impl Default for Annealing {
+    fn default() -> Self {
+        Self {
+            temperature: 0.5,
+            cooling: Cooling::Geometric,
+            generations: 300,
+            restarts: 1,
+        }
+    }
+}
+
+/// Simulated annealing over the same random swaps as `run_generations`, except a swap that makes
+/// things worse by `d` is still taken with probability `exp(-d / temperature)`. The best mapping
+/// seen at the end of any generation is what `pixels` holds afterwards, also when `budget` runs
+/// out early.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn run_annealing<S: ProgressSink>(
+    pixels: &mut [Pixel],
+    source_pixels: &[(u8, u8, u8)],
+    target: &[Features],
+    weights: &[i64],
+    settings: &GenerationSettings,
+    annealing: &Annealing,
+    tx: &mut S,
+    budget: &mut Budget,
+) -> Result<(), CalculateError> {
+    let mut rng = frand::Rand::with_seed(settings.seed);
+    let swaps = Swaps::new(target, weights, settings)?;
+    let swaps_per_generation = SWAPS_PER_GENERATION_PER_PIXEL * swaps.movable();
+    let generations = annealing.generations.max(1);
+    let runs = annealing.restarts + 1;
+    let (width, height) = settings.grid();
+
+    // costs relative to the starting mapping, only differences matter
+    let mut energy = 0;
+    let mut best_energy = 0;
+    let mut best = pixels.to_vec();
+
+    for run in 0..runs {
+        if run > 0 {
+            pixels.copy_from_slice(&best);
+            energy = best_energy;
+        }
+        // a warm start is already a good mapping too, grid-wide swaps would only scramble it
+        let start_dist = if run == 0 && settings.initial_assignment().is_none() {
+            settings.sidelen
+        } else {
+            REHEAT_DIST.min(settings.sidelen)
+        };
+        // cells outside the region never move, so they don't count towards the temperature
+        let mean_cost = swaps
+            .region
+            .cells(pixels.len())
+            .map(|cell| pixels[cell].h as f64)
+            .sum::<f64>()
+            / swaps.movable().max(1) as f64;
+        let start_temperature = annealing.temperature as f64 * mean_cost;
+
+        for generation in 0..generations {
+            let fraction = generation as f32 / generations as f32;
+            let temperature = start_temperature * annealing.cooling.factor(fraction) as f64;
+            let max_dist = (start_dist as f32 * (1.0 - fraction)).max(2.0) as u32;
+
+            for _ in 0..swaps_per_generation {
+                let (apos, bpos) = swaps.pick(&mut rng, max_dist);
+                let Some(swap) = swaps.evaluate(pixels, apos, bpos) else {
+                    continue;
+                };
+                if swap.improvement > 0
+                    || (temperature > 0.0
+                        && rng.r#gen::<f64>() < (swap.improvement as f64 / temperature).exp())
+                {
+                    swap.apply(pixels);
+                    energy -= swap.improvement;
+                }
+            }
+            if energy < best_energy {
+                best_energy = energy;
+                best.copy_from_slice(pixels);
+            }
+
+            budget.pause().await;
+            if budget.cancelled() {
+                return Err(CalculateError::Cancelled);
+            }
+            if budget.tick() {
+                pixels.copy_from_slice(&best);
+                return Ok(());
+            }
+
+            let data = make_new_img(source_pixels, &pixel_assignments(pixels, width));
+            tx.send(ProgressMsg::UpdatePreview {
+                width,
+                height,
+                data,
+            });
+            tx.send(ProgressMsg::Progress((run as f32 + fraction) / runs as f32));
+        }
+    }
+
+    pixels.copy_from_slice(&best);
+    Ok(())
+}
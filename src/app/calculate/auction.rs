@@ -0,0 +1,286 @@
+use std::sync::Arc;
+
+use std::collections::VecDeque;
+
+use crate::app::calculate::cost::CostModel;
+use crate::app::calculate::error::CalculateError;
+use crate::app::calculate::pins::Pins;
+use crate::app::calculate::region::Region;
+use crate::app::calculate::util::JobControl;
+use crate::app::calculate::util::{Budget, GenerationSettings, ProgressSink};
+use crate::app::calculate::{
+    ImgDiffWeights, ProgressMsg, Solved, Weights, complete_assignment, make_new_img, util,
+};
+use crate::app::preset::UnprocessedPreset;
+
+// each phase divides epsilon by this, fewer phases but more bidding per phase when larger
+const EPSILON_SCALING: i64 = 6;
+const BIDS_PER_UPDATE: usize = 1 << 16;
+// candidates remembered per row between full window scans
+const CACHED: usize = 16;
+
+struct Auction<'w, 'a> {
+    weights: &'w ImgDiffWeights<'a>,
+    radius: usize,
+    prices: Vec<i64>,
+    owner: Vec<Option<usize>>,
+    assigned: Vec<Option<usize>>,
+    queue: VecDeque<usize>,
+    max_spread: i64,
+    bids: usize,
+    // best columns of each row's last full scan, and the value of the best one left out.
+    // prices only go up, so nothing outside the cache can be worth more than that bound
+    cache: Vec<[usize; CACHED]>,
+    cache_len: Vec<u8>,
+    cache_bound: Vec<i64>,
+}
+
+impl Auction<'_, '_> {
+    fn value(&self, row: usize, col: usize) -> i64 {
+        self.weights.at(row, col) - self.prices[col]
+    }
+
+    /// Best column for `row` at current prices, its value, and the runner-up value.
+    fn best_two(&mut self, row: usize) -> (usize, i64, i64) {
+        let mut best_col = usize::MAX;
+        let mut best = i64::MIN;
+        let mut second = i64::MIN;
+        for &col in &self.cache[row][..self.cache_len[row] as usize] {
+            let value = self.value(row, col);
+            if value > best {
+                second = best;
+                best = value;
+                best_col = col;
+            } else if value > second {
+                second = value;
+            }
+        }
+        if best_col != usize::MAX && second >= self.cache_bound[row] {
+            return (best_col, best, second);
+        }
+
+        let weights = self.weights;
+        let (width, height) = (weights.width, weights.height);
+        let radius = self.radius;
+        let (x, y) = (row % width, row / width);
+        let target = weights.target[row];
+        let color_weight = weights.weights[row];
+        let spatial_weight = weights.settings.proximity_importance;
+        let cost = weights.settings.cost();
+
+        // top CACHED + 1 by value, best first
+        let mut top = [(i64::MIN, usize::MAX); CACHED + 1];
+        let mut consider = |col: usize, value: i64| {
+            if value > top[CACHED].0 {
+                let mut i = CACHED;
+                while i > 0 && top[i - 1].0 < value {
+                    top[i] = top[i - 1];
+                    i -= 1;
+                }
+                top[i] = (value, col);
+            }
+        };
+        // same candidates as `ImgDiffWeights::window`, walked by coordinate to skip the div/mod
+        for cy in y.saturating_sub(radius)..=(y + radius).min(height - 1) {
+            for cx in x.saturating_sub(radius)..=(x + radius).min(width - 1) {
+                let col = cy * width + cx;
+                if !weights.region.allows(row, col) {
+                    continue;
+                }
+                let value = -cost.cost(
+                    (x as u32, y as u32),
+                    (cx as u32, cy as u32),
+                    target,
+                    weights.source[col],
+                    color_weight,
+                    spatial_weight,
+                ) - weights
+                    .pins
+                    .cost((cx as u32, cy as u32), (x as u32, y as u32))
+                    - self.prices[col];
+                consider(col, value);
+            }
+        }
+        for col in weights.pin_window(row, radius) {
+            consider(col, weights.at(row, col) - self.prices[col]);
+        }
+
+        let len = top[..CACHED].iter().filter(|t| t.1 != usize::MAX).count();
+        for (slot, &(_, col)) in self.cache[row].iter_mut().zip(&top[..len]) {
+            *slot = col;
+        }
+        self.cache_len[row] = len as u8;
+        self.cache_bound[row] = top[CACHED].0;
+        (top[0].1, top[0].0, top[1].0)
+    }
+
+    fn bid(&mut self, row: usize, eps: i64) {
+        let (best_col, best, second) = self.best_two(row);
+        // a lone candidate can't be outbid by anything in its own window
+        let increment = if second == i64::MIN {
+            self.max_spread
+        } else {
+            best - second
+        } + eps;
+
+        self.prices[best_col] += increment;
+        if let Some(prev) = self.owner[best_col].replace(row) {
+            self.assigned[prev] = None;
+            self.queue.push_back(prev);
+        }
+        self.assigned[row] = Some(best_col);
+        self.bids += 1;
+    }
+
+    /// Drops every assignment except those of the cells outside the region, which keep their
+    /// own source and never bid.
+    fn reset(&mut self) {
+        self.owner.fill(None);
+        self.assigned.fill(None);
+        self.queue.clear();
+        for cell in 0..self.owner.len() {
+            if !self.weights.region.contains(cell) {
+                self.owner[cell] = Some(cell);
+                self.assigned[cell] = Some(cell);
+            }
+        }
+    }
+
+    /// Starts a phase from `assignments`, keeping only the rows that are within `eps` of their
+    /// best column at current prices and queueing the rest. Rows kept this way satisfy the same
+    /// invariant bidding maintains, so the phase ends just as close to optimal.
+    fn seed(&mut self, assignments: &[usize], eps: i64) {
+        self.reset();
+        for (row, &col) in assignments.iter().enumerate() {
+            if !self.weights.region.contains(row) {
+                continue;
+            }
+            let (_, best, _) = self.best_two(row);
+            if self.owner[col].is_none() && self.value(row, col) >= best - eps {
+                self.owner[col] = Some(row);
+                self.assigned[row] = Some(col);
+            } else {
+                self.queue.push_back(row);
+            }
+        }
+    }
+
+    fn current_assignments(&self) -> Vec<usize> {
+        self.assigned.iter().map(|a| a.unwrap_or(0)).collect()
+    }
+}
+
+/// Bertsekas auction with epsilon scaling. Targets (rows) bid for source cells (columns) within
+/// a spatial window around themselves; the final phase runs with epsilon = 1 so the result is
+/// within `n` of the optimal integer cost for that window. If the budget runs out first, the
+/// rows still bidding get whatever source cells are left.
+pub async fn process_auction<S: ProgressSink>(
+    unprocessed: UnprocessedPreset,
+    settings: GenerationSettings,
+    tx: &mut S,
+    control: Arc<JobControl>,
+) -> Result<Solved, CalculateError> {
+    let source_img = util::source_image(&unprocessed)?;
+    let util::Images {
+        source_pixels,
+        source,
+        target,
+        weights,
+    } = util::get_images(source_img, &settings)?;
+    let (width, height) = settings.grid();
+
+    let weights = ImgDiffWeights {
+        source,
+        target,
+        weights,
+        width: width as usize,
+        height: height as usize,
+        settings: &settings,
+        pins: Pins::new(&settings),
+        region: Region::new(&settings)?,
+    };
+
+    let n = weights.rows();
+    let radius = weights.solver_radius();
+
+    // largest benefit spread inside any window, used as the starting epsilon
+    let max_spread = weights.max_spread(radius);
+
+    let mut epsilons = vec![];
+    let mut eps = (max_spread / 4).max(1);
+    loop {
+        epsilons.push(eps);
+        if eps == 1 {
+            break;
+        }
+        eps = (eps / EPSILON_SCALING).max(1);
+    }
+
+    let mut auction = Auction {
+        weights: &weights,
+        radius,
+        prices: vec![0; n],
+        owner: vec![None; n],
+        assigned: vec![None; n],
+        queue: VecDeque::with_capacity(n),
+        max_spread,
+        bids: 0,
+        cache: vec![[0; CACHED]; n],
+        cache_len: vec![0; n],
+        cache_bound: vec![i64::MIN; n],
+    };
+
+    // a warm start seeds the first phase, and then each phase's assignment carries into the next.
+    // Prices still have to be found from scratch, so unlike the swap solvers it saves little time
+    let warm_start = settings.initial_assignment();
+    let mut budget = Budget::new(&settings, control);
+
+    'phases: for (phase, &eps) in epsilons.iter().enumerate() {
+        if let Some(start) = warm_start {
+            let start = if phase == 0 {
+                let mut start = start.to_vec();
+                weights.region.confine(&mut start);
+                start
+            } else {
+                auction.current_assignments()
+            };
+            auction.seed(&start, eps);
+        } else {
+            // prices carry over between phases, assignments do not
+            auction.reset();
+            auction.queue.extend(weights.region.cells(n));
+        }
+
+        while let Some(row) = auction.queue.pop_front() {
+            auction.bid(row, eps);
+
+            if auction.bids % BIDS_PER_UPDATE == 0 {
+                budget.pause().await;
+                if budget.cancelled() {
+                    return Err(CalculateError::Cancelled);
+                }
+                if budget.tick() {
+                    break 'phases;
+                }
+
+                let phase_progress =
+                    1.0 - auction.queue.len() as f32 / weights.region.len(n).max(1) as f32;
+                tx.send(ProgressMsg::Progress(
+                    (phase as f32 + phase_progress) / epsilons.len() as f32,
+                ));
+
+                let data = make_new_img(&source_pixels, &auction.current_assignments());
+                tx.send(ProgressMsg::UpdatePreview {
+                    width,
+                    height,
+                    data,
+                });
+            }
+        }
+    }
+
+    Ok(Solved {
+        source_pixels,
+        assignments: complete_assignment(&auction.assigned),
+    })
+}
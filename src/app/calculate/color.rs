@@ -0,0 +1,88 @@
+use palette::color_difference::Ciede2000;
+use palette::{IntoColor, Lab, LinSrgb, Srgb};
+use serde::{Deserialize, Serialize};
+
+// black to white is ΔE 100 but 255² * 3 in sRGB, perceptual distances are scaled by this so
+// proximity importance means about the same in every colour space
+const LAB_SCALE: i64 = 20;
+// largest ΔE between two sRGB colours, used to bound candidate windows
+const MAX_LAB_DISTANCE: i64 = 260;
+const MAX_CIEDE2000_DISTANCE: i64 = 120;
+// linear light is stored at 16 bits, a step of 257 is one step of an 8 bit channel
+const LINEAR_STEP: i64 = 257;
+
+/// Colour space the cost measures colour differences in. `get_images` encodes every cell once,
+/// so the cost only compares encoded triples.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ColorSpace {
+    /// Squared sRGB distance.
+    Rgb,
+    /// Squared distance in linear light, stored at 16 bits so dark tones stay apart.
+    LinearRgb,
+    /// CIE76: squared euclidean distance in CIELAB.
+    Lab,
+    /// CIEDE2000, corrects CIELAB for hue and saturation. Too slow to compare in the solvers, so
+    /// they match in CIELAB and a last round of local swaps compares in CIEDE2000, see
+    /// `process`.
+    Ciede2000,
+}
+
+impl ColorSpace {
+    /// Encodes an sRGB colour. Linear light is stored at 16 bits, CIELAB channels as
+    /// `(L, a + 128, b + 128)`.
+    pub fn encode(&self, rgb: (u8, u8, u8)) -> (u16, u16, u16) {
+        let srgb = Srgb::new(rgb.0, rgb.1, rgb.2).into_format::<f32>();
+        match self {
+            ColorSpace::Rgb => (rgb.0 as u16, rgb.1 as u16, rgb.2 as u16),
+            ColorSpace::LinearRgb => {
+                let linear: LinSrgb<u16> = srgb.into_linear().into_format();
+                (linear.red, linear.green, linear.blue)
+            }
+            ColorSpace::Lab | ColorSpace::Ciede2000 => {
+                let lab: Lab = srgb.into_color();
+                (
+                    lab.l.round().clamp(0.0, 255.0) as u16,
+                    (lab.a + 128.0).round().clamp(0.0, 255.0) as u16,
+                    (lab.b + 128.0).round().clamp(0.0, 255.0) as u16,
+                )
+            }
+        }
+    }
+
+    /// Squared difference between two encoded colours times `weight`, on the scale of squared
+    /// sRGB distance. The weight goes in before rounding, so the small differences linear light
+    /// has between dark tones don't all round to nothing.
+    #[inline(always)]
+    pub fn distance(&self, a: (u16, u16, u16), b: (u16, u16, u16), weight: i64) -> i64 {
+        match self {
+            ColorSpace::Rgb => squared(a, b) * weight,
+            ColorSpace::LinearRgb => squared(a, b) * weight / (LINEAR_STEP * LINEAR_STEP),
+            ColorSpace::Lab => squared(a, b) * LAB_SCALE * weight,
+            ColorSpace::Ciede2000 => {
+                let delta = decode_lab(a).difference(decode_lab(b));
+                (delta * delta * (LAB_SCALE * weight) as f32) as i64
+            }
+        }
+    }
+
+    /// Upper bound of `distance` between any two colours at weight 1.
+    pub fn max_distance(&self) -> i64 {
+        match self {
+            ColorSpace::Rgb | ColorSpace::LinearRgb => 3 * 255 * 255,
+            ColorSpace::Lab => MAX_LAB_DISTANCE.pow(2) * LAB_SCALE,
+            ColorSpace::Ciede2000 => MAX_CIEDE2000_DISTANCE.pow(2) * LAB_SCALE,
+        }
+    }
+}
+
+#[inline(always)]
+pub(super) fn squared<T: Into<i64>>(a: (T, T, T), b: (T, T, T)) -> i64 {
+    (a.0.into() - b.0.into()).pow(2)
+        + (a.1.into() - b.1.into()).pow(2)
+        + (a.2.into() - b.2.into()).pow(2)
+}
+
+#[inline(always)]
+fn decode_lab(c: (u16, u16, u16)) -> Lab {
+    Lab::new(c.0 as f32, c.1 as f32 - 128.0, c.2 as f32 - 128.0)
+}
@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+
+use crate::app::calculate::color::ColorSpace;
+use crate::app::calculate::texture;
+
+/// What the cost compares for one cell.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Features {
+    /// Colour encoded in the job's `ColorSpace`.
+    pub color: (u16, u16, u16),
+    /// Local structure around the cell, see `texture::describe`.
+    pub texture: (u8, u8, u8),
+}
+
+/// How different two cells look: colour distance plus texture distance times
+/// `texture_importance`, both times the target cell's weight.
+#[derive(Clone, Copy)]
+pub struct Appearance {
+    pub color_space: ColorSpace,
+    pub texture_importance: i64,
+}
+
+impl Appearance {
+    #[inline(always)]
+    pub fn difference(&self, a: Features, b: Features, weight: i64) -> i64 {
+        let texture = if self.texture_importance == 0 {
+            0
+        } else {
+            texture::distance(a.texture, b.texture) * self.texture_importance * weight
+        };
+        self.color_space.distance(a.color, b.color, weight) + texture
+    }
+
+    /// Upper bound of `difference` between any two cells at weight 1.
+    pub fn max_difference(&self) -> i64 {
+        self.color_space.max_distance() + texture::MAX_DISTANCE * self.texture_importance
+    }
+}
+
+/// How much it costs to send a source cell to a target cell. Every solver and the drawing process
+/// go through this, so a new formulation only needs a new implementation and a `CostFunction`
+/// variant to become selectable.
+pub trait CostModel {
+    /// Cost of matching the cell at `apos` with features `a` to the one at `bpos` with features
+    /// `b`. `color_weight` is the target cell's importance, `spatial_weight` the proximity
+    /// importance.
+    fn cost(
+        &self,
+        apos: (u32, u32),
+        bpos: (u32, u32),
+        a: Features,
+        b: Features,
+        color_weight: i64,
+        spatial_weight: i64,
+    ) -> i64;
+
+    /// Distance (in cells) past which the spatial term alone is larger than `cost`.
+    fn reach(&self, cost: i64, spatial_weight: i64) -> usize;
+
+    /// Spatial weight that keeps the spatial term as heavy when every distance is divided by
+    /// `scale`, as on a coarser grid.
+    fn rescale(&self, spatial_weight: i64, scale: f32) -> i64;
+}
+
+#[inline(always)]
+fn squared_distance(apos: (u32, u32), bpos: (u32, u32)) -> i64 {
+    (apos.0 as i64 - bpos.0 as i64).pow(2) + (apos.1 as i64 - bpos.1 as i64).pow(2)
+}
+
+/// Appearance difference plus `(distance² * spatial_weight)²`. Long moves get very expensive, so
+/// cells stay close to home unless the colour gain is large.
+pub struct Quartic(pub Appearance);
+
+impl CostModel for Quartic {
+    #[inline(always)]
+    fn cost(
+        &self,
+        apos: (u32, u32),
+        bpos: (u32, u32),
+        a: Features,
+        b: Features,
+        color_weight: i64,
+        spatial_weight: i64,
+    ) -> i64 {
+        self.0.difference(a, b, color_weight)
+            + (squared_distance(apos, bpos) * spatial_weight).pow(2)
+    }
+
+    fn reach(&self, cost: i64, spatial_weight: i64) -> usize {
+        ((cost as f64).sqrt() / spatial_weight as f64).sqrt().ceil() as usize + 1
+    }
+
+    fn rescale(&self, spatial_weight: i64, scale: f32) -> i64 {
+        (spatial_weight as f32 * scale * scale).round() as i64
+    }
+}
+
+/// Appearance difference plus `distance² * spatial_weight²`. Agrees with `Quartic` for
+/// neighbours but is far more lenient with long moves, which gives more dramatic morphs.
+pub struct Quadratic(pub Appearance);
+
+impl CostModel for Quadratic {
+    #[inline(always)]
+    fn cost(
+        &self,
+        apos: (u32, u32),
+        bpos: (u32, u32),
+        a: Features,
+        b: Features,
+        color_weight: i64,
+        spatial_weight: i64,
+    ) -> i64 {
+        self.0.difference(a, b, color_weight) + squared_distance(apos, bpos) * spatial_weight.pow(2)
+    }
+
+    fn reach(&self, cost: i64, spatial_weight: i64) -> usize {
+        ((cost as f64).sqrt() / spatial_weight as f64).ceil() as usize + 1
+    }
+
+    fn rescale(&self, spatial_weight: i64, scale: f32) -> i64 {
+        (spatial_weight as f32 * scale).round() as i64
+    }
+}
+
+/// The cost models that can be picked in `GenerationSettings`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum CostFunction {
+    Quartic,
+    Quadratic,
+}
+
+/// The cost picked in `GenerationSettings`, see `GenerationSettings::cost`.
+#[derive(Clone, Copy)]
+pub struct Cost {
+    pub function: CostFunction,
+    pub appearance: Appearance,
+}
+
+impl CostModel for Cost {
+    #[inline(always)]
+    fn cost(
+        &self,
+        apos: (u32, u32),
+        bpos: (u32, u32),
+        a: Features,
+        b: Features,
+        color_weight: i64,
+        spatial_weight: i64,
+    ) -> i64 {
+        match self.function {
+            CostFunction::Quartic => {
+                Quartic(self.appearance).cost(apos, bpos, a, b, color_weight, spatial_weight)
+            }
+            CostFunction::Quadratic => {
+                Quadratic(self.appearance).cost(apos, bpos, a, b, color_weight, spatial_weight)
+            }
+        }
+    }
+
+    fn reach(&self, cost: i64, spatial_weight: i64) -> usize {
+        match self.function {
+            CostFunction::Quartic => Quartic(self.appearance).reach(cost, spatial_weight),
+            CostFunction::Quadratic => Quadratic(self.appearance).reach(cost, spatial_weight),
+        }
+    }
+
+    fn rescale(&self, spatial_weight: i64, scale: f32) -> i64 {
+        match self.function {
+            CostFunction::Quartic => Quartic(self.appearance).rescale(spatial_weight, scale),
+            CostFunction::Quadratic => Quadratic(self.appearance).rescale(spatial_weight, scale),
+        }
+    }
+}
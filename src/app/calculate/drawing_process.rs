@@ -9,7 +9,8 @@ use std::sync::Arc;
 use std::sync::atomic::AtomicU32;
 use std::sync::mpsc;
 
-use super::ProgressMsg;
+use super::parallel::TileCells;
+use super::{Cells, JobMsg, ProgressMsg};
 
 use super::GenerationSettings;
 
@@ -32,17 +33,17 @@ impl PixelData {
 
 pub const DRAWING_CANVAS_SIZE: usize = 128;
 
-use super::heuristic;
+use super::cost::{CostModel, Features};
 
 #[derive(Clone, Copy)]
 pub(crate) struct DrawingPixel {
-    pub(crate) src_x: u16,
-    pub(crate) src_y: u16,
+    pub(crate) src_x: u32,
+    pub(crate) src_y: u32,
     pub(crate) h: i64, // current heuristic value
 }
 
 impl DrawingPixel {
-    pub(crate) fn new(src_x: u16, src_y: u16, h: i64) -> Self {
+    pub(crate) fn new(src_x: u32, src_y: u32, h: i64) -> Self {
         Self { src_x, src_y, h }
     }
 
@@ -53,42 +54,49 @@ impl DrawingPixel {
     #[inline(always)]
     pub(crate) fn calc_drawing_heuristic(
         &self,
-        target_pos: (u16, u16),
-        target_col: (u8, u8, u8),
+        target_pos: (u32, u32),
+        target_features: Features,
         weight: i64,
-        colors: &[SeedColor],
+        colors: &[Features],
+        cost: &impl CostModel,
         proximity_importance: i64,
     ) -> i64 {
-        heuristic(
+        cost.cost(
             (self.src_x, self.src_y),
             target_pos,
-            {
-                let rgba =
-                    colors[self.src_y as usize * DRAWING_CANVAS_SIZE + self.src_x as usize].rgba;
-                (
-                    (rgba[0] * 256.0) as u8,
-                    (rgba[1] * 256.0) as u8,
-                    (rgba[2] * 256.0) as u8,
-                )
-            },
-            target_col,
+            colors[self.src_y as usize * DRAWING_CANVAS_SIZE + self.src_x as usize],
+            target_features,
             weight,
             proximity_importance,
         )
     }
 }
 
+/// Cost features of the canvas colours.
+fn canvas_features(colors: &[SeedColor], settings: &GenerationSettings) -> Vec<Features> {
+    let pixels = colors
+        .iter()
+        .map(|c| {
+            (
+                (c.rgba[0] * 256.0) as u8,
+                (c.rgba[1] * 256.0) as u8,
+                (c.rgba[2] * 256.0) as u8,
+            )
+        })
+        .collect::<Vec<_>>();
+    settings.features(&pixels)
+}
+
 pub(crate) const STROKE_REWARD: i64 = -10000000000;
 
-pub(crate) fn stroke_reward(
+fn stroke_reward(
     newpos: usize,
     oldpos: usize,
     pixel_data: &[PixelData],
-    pixels: &[DrawingPixel],
+    pixels: &TileCells<'_, DrawingPixel>,
     frame_count: u32,
 ) -> i64 {
-    let x = (newpos % DRAWING_CANVAS_SIZE) as u16;
-    let y = (newpos / DRAWING_CANVAS_SIZE) as u16;
+    let (x, y) = calculate::cell_pos(newpos, DRAWING_CANVAS_SIZE);
     // look at 8-connected neighbors
     // if any has the same stroke_id, return true
     let data = pixel_data
@@ -106,9 +114,9 @@ pub(crate) fn stroke_reward(
         (0, 1),
         //(1, 1),
     ] {
-        let nx = x as i16 + dx;
-        let ny = y as i16 + dy;
-        if nx < 0 || nx >= DRAWING_CANVAS_SIZE as i16 || ny < 0 || ny >= DRAWING_CANVAS_SIZE as i16
+        let nx = x as i64 + dx;
+        let ny = y as i64 + dy;
+        if nx < 0 || nx >= DRAWING_CANVAS_SIZE as i64 || ny < 0 || ny >= DRAWING_CANVAS_SIZE as i64
         {
             continue;
         }
@@ -128,35 +136,40 @@ pub(crate) fn stroke_reward(
 pub fn drawing_process_genetic(
     source: UnprocessedPreset,
     settings: GenerationSettings,
-    tx: mpsc::SyncSender<ProgressMsg>,
+    tx: mpsc::SyncSender<JobMsg>,
     colors: Arc<std::sync::RwLock<Vec<SeedColor>>>,
     pixel_data: Arc<std::sync::RwLock<Vec<PixelData>>>,
     frame_count: u32,
     my_id: u32,
     current_id: Arc<AtomicU32>,
 ) -> Result<(), Box<dyn Error>> {
-    let source_img =
-        image::ImageBuffer::from_raw(source.width, source.height, source.source_img.clone())
-            .unwrap();
-    let (source_pixels, target_pixels, weights) =
-        calculate::util::get_images(source_img, &settings)?;
+    let source_img = calculate::util::source_image(&source)?;
+    let calculate::util::Images {
+        source_pixels,
+        target,
+        weights,
+        ..
+    } = calculate::util::get_images(source_img, &settings)?;
+    let cost = settings.cost();
+    let (width, height) = settings.grid();
+    let (width, height) = (width as usize, height as usize);
 
     let mut pixels = {
-        let read_colors: Vec<SeedColor> = colors.read().unwrap().clone();
+        let read_colors = canvas_features(&colors.read().unwrap(), &settings);
         //let read_pixel_data: Vec<PixelData> = pixel_data.read().unwrap().clone();
 
         source_pixels
             .iter()
             .enumerate()
             .map(|(i, _)| {
-                let x = (i as u32 % settings.sidelen) as u16;
-                let y = (i as u32 / settings.sidelen) as u16;
+                let (x, y) = calculate::cell_pos(i, width);
                 let mut p = DrawingPixel::new(x, y, 0);
                 let h = p.calc_drawing_heuristic(
                     (x, y),
-                    target_pixels[i],
+                    target[i],
                     weights[i],
                     &read_colors,
+                    &cost,
                     settings.proximity_importance,
                     // &read_pixel_data,
                 ) + STROKE_REWARD;
@@ -166,63 +179,58 @@ pub fn drawing_process_genetic(
             .collect::<Vec<_>>()
     };
 
-    let mut rng = frand::Rand::with_seed(12345);
+    let mut rng = frand::Rand::with_seed(settings.seed);
     fn max_dist(age: u32) -> u32 {
         (((DRAWING_CANVAS_SIZE / 4) as f32) * (0.99f32).powi(age as i32 / 30)).round() as u32
     }
 
     let swaps_per_generation = SWAPS_PER_GENERATION_PER_PIXEL * pixels.len();
+    let mut tiled = settings
+        .parallel
+        .then(|| calculate::parallel::Tiled::new(width, height));
 
+    let mut generation = 0;
     loop {
-        let colors: Vec<SeedColor> = {
-            let r = colors.read().unwrap();
-            r.clone()
-        };
+        let colors = canvas_features(&colors.read().unwrap(), &settings);
         let pixel_data = {
             let r = pixel_data.read().unwrap();
             r.clone()
         };
-        let mut swaps_made = 0;
-
-        for _ in 0..swaps_per_generation {
-            let apos = rng.gen_range(0..pixels.len() as u64) as usize;
-            let ax = apos as u16 % settings.sidelen as u16;
-            let ay = apos as u16 / settings.sidelen as u16;
-
-            //let stroke_id = pixel_data[apos].stroke_id as usize;
-            let max_dist_a = max_dist(frame_count.saturating_sub(pixel_data[apos].last_edited));
-
-            let bx = (ax as i16 + rng.gen_range(-(max_dist_a as i16)..(max_dist_a as i16 + 1)))
-                .clamp(0, settings.sidelen as i16 - 1) as u16;
-            let by = (ay as i16 + rng.gen_range(-(max_dist_a as i16)..(max_dist_a as i16 + 1)))
-                .clamp(0, settings.sidelen as i16 - 1) as u16;
-            let bpos = by as usize * settings.sidelen as usize + bx as usize;
+        let max_dist_at =
+            |pos: usize| max_dist(frame_count.saturating_sub(pixel_data[pos].last_edited));
 
-            let max_dist_b = max_dist(frame_count.saturating_sub(pixel_data[bpos].last_edited));
+        // swaps the sources of two canvas cells if that's cheaper, `bpos` has to be in reach of
+        // both cells
+        let try_swap = |pixels: &mut TileCells<'_, DrawingPixel>, apos: usize, bpos: usize| {
+            let (ax, ay) = calculate::cell_pos(apos, width);
+            let (bx, by) = calculate::cell_pos(bpos, width);
+            let max_dist_b = max_dist_at(bpos);
             if (bx as i32 - ax as i32).abs() > max_dist_b as i32
                 || (by as i32 - ay as i32).abs() > max_dist_b as i32
             {
-                continue;
+                return false;
             }
 
-            let t_a = target_pixels[apos];
-            let t_b = target_pixels[bpos];
+            let t_a = target[apos];
+            let t_b = target[bpos];
 
             let a_on_b_h = pixels[apos].calc_drawing_heuristic(
                 (bx, by),
                 t_b,
                 weights[bpos],
                 &colors,
+                &cost,
                 settings.proximity_importance,
-            ) + stroke_reward(bpos, apos, &pixel_data, &pixels, frame_count);
+            ) + stroke_reward(bpos, apos, &pixel_data, pixels, frame_count);
 
             let b_on_a_h = pixels[bpos].calc_drawing_heuristic(
                 (ax, ay),
                 t_a,
                 weights[apos],
                 &colors,
+                &cost,
                 settings.proximity_importance,
-            ) + stroke_reward(apos, bpos, &pixel_data, &pixels, frame_count);
+            ) + stroke_reward(apos, bpos, &pixel_data, pixels, frame_count);
 
             let improvement_a = pixels[apos].h - b_on_a_h;
             let improvement_b = pixels[bpos].h - a_on_b_h;
@@ -231,9 +239,48 @@ pub fn drawing_process_genetic(
                 pixels.swap(apos, bpos);
                 pixels[apos].update_heuristic(b_on_a_h);
                 pixels[bpos].update_heuristic(a_on_b_h);
-                swaps_made += 1;
+                return true;
             }
-        }
+            false
+        };
+
+        let swaps_made = if let Some(tiled) = &mut tiled {
+            tiled.generation(
+                &mut pixels,
+                calculate::tile_side(&settings),
+                settings.seed.wrapping_add(generation),
+                |pixels, tile, rng| {
+                    (0..SWAPS_PER_GENERATION_PER_PIXEL * tile.cells())
+                        .filter(|_| {
+                            let apos = tile.cell(rng, width);
+                            let bpos = tile.partner(rng, apos, max_dist_at(apos), width);
+                            try_swap(pixels, apos, bpos)
+                        })
+                        .count()
+                },
+            )
+        } else {
+            let mut swaps_made = 0;
+            let cell_count = pixels.len();
+            let mut cells = TileCells::whole(&mut pixels, width, height);
+            for _ in 0..swaps_per_generation {
+                let apos = rng.gen_range(0..cell_count as u64) as usize;
+                //let stroke_id = pixel_data[apos].stroke_id as usize;
+                let max_dist_a = max_dist_at(apos) as i64;
+                let (ax, ay) = calculate::cell_pos(apos, width);
+
+                let bx = (ax as i64 + rng.gen_range(-max_dist_a..max_dist_a + 1))
+                    .clamp(0, width as i64 - 1);
+                let by = (ay as i64 + rng.gen_range(-max_dist_a..max_dist_a + 1))
+                    .clamp(0, height as i64 - 1);
+                let bpos = (by * width as i64 + bx) as usize;
+
+                if try_swap(&mut cells, apos, bpos) {
+                    swaps_made += 1;
+                }
+            }
+            swaps_made
+        };
 
         //println!("swaps made: {}", swaps_made);
 
@@ -250,14 +297,22 @@ pub fn drawing_process_genetic(
         if swaps_made > 0 {
             let assignments = pixels
                 .iter()
-                .map(|p| p.src_y as usize * settings.sidelen as usize + p.src_x as usize)
+                .map(|p| p.src_y as usize * width + p.src_x as usize)
                 .collect::<Vec<_>>();
-            tx.send(ProgressMsg::UpdateAssignments(assignments))?;
+            tx.send(JobMsg {
+                id: settings.id,
+                msg: ProgressMsg::UpdateAssignments(assignments),
+            })?;
         }
         if my_id != current_id.load(std::sync::atomic::Ordering::Relaxed) {
-            tx.send(ProgressMsg::Cancelled).unwrap();
+            tx.send(JobMsg {
+                id: settings.id,
+                msg: ProgressMsg::Cancelled,
+            })
+            .unwrap();
             return Ok(());
         }
+        generation += 1;
 
         //max_dist = (max_dist as f32 * 0.99).max(4.0) as u32;
     }
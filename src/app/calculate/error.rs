@@ -0,0 +1,86 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ImageKind {
+    Source,
+    Target,
+    Weights,
+    Mask,
+}
+
+impl fmt::Display for ImageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ImageKind::Source => "source image",
+            ImageKind::Target => "target image",
+            ImageKind::Weights => "weight map",
+            ImageKind::Mask => "region mask",
+        })
+    }
+}
+
+/// How a job can fail, sent to the GUI in `ProgressMsg::Error`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CalculateError {
+    /// An image with no pixels, or whose buffer doesn't hold `width * height` of them.
+    InvalidDimensions {
+        image: ImageKind,
+        width: u32,
+        height: u32,
+    },
+    /// Per-cell data that doesn't cover the grid cell for cell.
+    MismatchedBuffers {
+        image: ImageKind,
+        expected: usize,
+        found: usize,
+    },
+    Cancelled,
+    /// Something that can't be fixed from the GUI, e.g. a bundled image that fails to decode.
+    Internal(String),
+}
+
+impl fmt::Display for CalculateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalculateError::InvalidDimensions {
+                image,
+                width,
+                height,
+            } if *width == 0 || *height == 0 => {
+                write!(
+                    f,
+                    "the {image} is empty ({width}×{height}), pick another one"
+                )
+            }
+            CalculateError::InvalidDimensions {
+                image,
+                width,
+                height,
+            } => write!(
+                f,
+                "the {image}'s pixels don't add up to {width}×{height}, try loading it again"
+            ),
+            CalculateError::MismatchedBuffers {
+                image,
+                expected,
+                found,
+            } => write!(
+                f,
+                "the {image} covers {found} cells but the grid has {expected}, \
+                 try loading it again"
+            ),
+            CalculateError::Cancelled => f.write_str("cancelled"),
+            CalculateError::Internal(msg) => write!(f, "internal error: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for CalculateError {}
+
+impl From<image::ImageError> for CalculateError {
+    fn from(err: image::ImageError) -> Self {
+        CalculateError::Internal(err.to_string())
+    }
+}
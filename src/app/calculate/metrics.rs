@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+
+use crate::app::calculate::cost::CostModel;
+use crate::app::calculate::error::CalculateError;
+use crate::app::calculate::pins::Pins;
+use crate::app::calculate::util::GenerationSettings;
+use crate::app::calculate::{cell_pos, make_new_img};
+
+// SSIM window side in cells and the step between windows
+const SSIM_WINDOW: usize = 8;
+const SSIM_STEP: usize = 4;
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// How good a finished assignment is, measured against the cropped target it was solved for.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Metrics {
+    /// Sum of the cost every cell pays where it ended up, lower is better. Only comparable
+    /// between runs with the same cost settings.
+    pub total_cost: i64,
+    /// How far cells travel, in cells.
+    pub mean_displacement: f32,
+    pub max_displacement: f32,
+    /// Peak signal-to-noise ratio of the morphed image against the target in dB, infinite when
+    /// they are identical.
+    pub psnr: f32,
+    /// Structural similarity of the morphed image and the target on luma, 1 is identical.
+    pub ssim: f32,
+    /// Cells the solver handed in with a move the region or maximum displacement doesn't
+    /// allow, which had to take another source cell.
+    #[serde(default)]
+    pub reverted_cells: usize,
+}
+
+impl Metrics {
+    /// Metrics for `assignments` (target cell -> source cell) over `source_pixels`, laid out on
+    /// `settings.grid()`.
+    pub(crate) fn measure(
+        settings: &GenerationSettings,
+        source_pixels: &[(u8, u8, u8)],
+        assignments: &[usize],
+    ) -> Result<Self, CalculateError> {
+        let (width, height) = settings.grid();
+        let (width, height) = (width as usize, height as usize);
+        let (target, weights) = settings.get_target()?;
+        let target_pixels = target
+            .pixels()
+            .map(|p| (p[0], p[1], p[2]))
+            .collect::<Vec<_>>();
+
+        let cell_costs = costs(
+            settings,
+            source_pixels,
+            &target_pixels,
+            &weights,
+            assignments,
+        );
+        let mut total_displacement = 0.0;
+        let mut max_displacement = 0.0f32;
+        for (i, &src) in assignments.iter().enumerate() {
+            let from = cell_pos(src, width);
+            let to = cell_pos(i, width);
+            let dx = from.0 as f32 - to.0 as f32;
+            let dy = from.1 as f32 - to.1 as f32;
+            let d = (dx * dx + dy * dy).sqrt();
+            total_displacement += d as f64;
+            max_displacement = max_displacement.max(d);
+        }
+
+        let morphed = make_new_img(source_pixels, assignments);
+        let target_raw = target_pixels
+            .iter()
+            .flat_map(|&(r, g, b)| [r, g, b])
+            .collect::<Vec<_>>();
+
+        Ok(Self {
+            total_cost: cell_costs.iter().sum(),
+            mean_displacement: (total_displacement / assignments.len().max(1) as f64) as f32,
+            max_displacement,
+            psnr: psnr(&morphed, &target_raw),
+            ssim: ssim(&luma(&morphed), &luma(&target_raw), width, height),
+            reverted_cells: 0,
+        })
+    }
+}
+
+/// What each target cell pays for the source cell it got in `assignments`, summing to the
+/// metrics' `total_cost`.
+pub(crate) fn cell_costs(
+    settings: &GenerationSettings,
+    source_pixels: &[(u8, u8, u8)],
+    assignments: &[usize],
+) -> Result<Vec<i64>, CalculateError> {
+    let (target, weights) = settings.get_target()?;
+    let target_pixels = target
+        .pixels()
+        .map(|p| (p[0], p[1], p[2]))
+        .collect::<Vec<_>>();
+    Ok(costs(
+        settings,
+        source_pixels,
+        &target_pixels,
+        &weights,
+        assignments,
+    ))
+}
+
+fn costs(
+    settings: &GenerationSettings,
+    source_pixels: &[(u8, u8, u8)],
+    target_pixels: &[(u8, u8, u8)],
+    weights: &[i64],
+    assignments: &[usize],
+) -> Vec<i64> {
+    let width = settings.grid().0 as usize;
+    let cost = settings.cost();
+    let pins = Pins::new(settings);
+    let source = settings.features(source_pixels);
+    let target = settings.features(target_pixels);
+    assignments
+        .iter()
+        .enumerate()
+        .map(|(i, &src)| {
+            let from = cell_pos(src, width);
+            let to = cell_pos(i, width);
+            cost.cost(
+                from,
+                to,
+                source[src],
+                target[i],
+                weights[i],
+                settings.proximity_importance,
+            ) + pins.cost(from, to)
+        })
+        .collect()
+}
+
+fn psnr(a: &[u8], b: &[u8]) -> f32 {
+    let sum = a
+        .iter()
+        .zip(b)
+        .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+        .sum::<f64>();
+    let mse = sum / a.len().max(1) as f64;
+    if mse == 0.0 {
+        return f32::INFINITY;
+    }
+    (10.0 * (255.0 * 255.0 / mse).log10()) as f32
+}
+
+/// Rec. 601 luma of packed RGB bytes.
+fn luma(rgb: &[u8]) -> Vec<f64> {
+    rgb.chunks_exact(3)
+        .map(|p| 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64)
+        .collect()
+}
+
+/// Mean SSIM over square windows of a `width`×`height` grid, uniformly weighted.
+fn ssim(a: &[f64], b: &[f64], width: usize, height: usize) -> f32 {
+    let window = SSIM_WINDOW.min(width).min(height);
+    let starts = |len: usize| (0..=len - window).step_by(SSIM_STEP).collect::<Vec<_>>();
+    let (xs, ys) = (starts(width), starts(height));
+    let n = (window * window) as f64;
+    let mut total = 0.0;
+    for &y0 in &ys {
+        for &x0 in &xs {
+            let cells =
+                (y0..y0 + window).flat_map(|y| (x0..x0 + window).map(move |x| y * width + x));
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for i in cells {
+                let (a, b) = (a[i], b[i]);
+                sa += a;
+                sb += b;
+                saa += a * a;
+                sbb += b * b;
+                sab += a * b;
+            }
+            let (ma, mb) = (sa / n, sb / n);
+            let va = saa / n - ma * ma;
+            let vb = sbb / n - mb * mb;
+            let cov = sab / n - ma * mb;
+            total += ((2.0 * ma * mb + SSIM_C1) * (2.0 * cov + SSIM_C2))
+                / ((ma * ma + mb * mb + SSIM_C1) * (va + vb + SSIM_C2));
+        }
+    }
+    (total / (xs.len() * ys.len()) as f64) as f32
+}
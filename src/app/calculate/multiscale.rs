@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use crate::app::calculate::cost::CostModel;
+use crate::app::calculate::error::CalculateError;
+use crate::app::calculate::pins::Pins;
+use crate::app::calculate::region::{self, Region};
+use crate::app::calculate::util::JobControl;
+use crate::app::calculate::util::{Budget, GenerationSettings, ProgressSink};
+use crate::app::calculate::{
+    ImgDiffWeights, ProgressMsg, REFINE_DIST, Solved, complete_assignment, make_new_img,
+    make_pixels, pixel_assignments, run_generations, solve_exact, util,
+};
+use crate::app::preset::UnprocessedPreset;
+
+// longer side of the coarsest level, solved exactly
+const BASE_SIDELEN: u32 = 32;
+
+/// Coarse-to-fine solve: the coarsest level gets an exact assignment, every finer level starts
+/// from the upsampled mapping of the one below and is refined with windowed swaps. Once the
+/// budget runs out the remaining levels are only upsampled.
+pub async fn process_multiscale<S: ProgressSink>(
+    unprocessed: UnprocessedPreset,
+    settings: GenerationSettings,
+    tx: &mut S,
+    control: Arc<JobControl>,
+) -> Result<Solved, CalculateError> {
+    let mut levels = vec![settings.sidelen];
+    while *levels.last().unwrap() > BASE_SIDELEN {
+        levels.push(levels.last().unwrap().div_ceil(2));
+    }
+    levels.reverse();
+
+    // progress is split between levels by their cell count
+    let total_cells = levels
+        .iter()
+        .map(|&s| {
+            let (w, h) = settings.grid_at(s);
+            (w * h) as f32
+        })
+        .sum::<f32>();
+    let mut done_cells = 0.0;
+
+    let mut budget = Budget::new(&settings, control);
+    let mut assignments: Vec<usize> = vec![];
+    let mut source_pixels = vec![];
+    for (i, &sidelen) in levels.iter().enumerate() {
+        let level_settings = level_settings(&settings, sidelen);
+        let source_img = util::source_image(&unprocessed)?;
+        let util::Images {
+            source_pixels: level_pixels,
+            source,
+            target,
+            weights,
+        } = util::get_images(source_img, &level_settings)?;
+        source_pixels = level_pixels;
+
+        let (width, height) = level_settings.grid();
+        let cells = (width * height) as f32;
+        let progress = |p: f32| (done_cells + p * cells) / total_cells;
+
+        if i == 0 {
+            let n = target.len();
+            let weights = ImgDiffWeights {
+                source,
+                target,
+                weights,
+                width: width as usize,
+                height: height as usize,
+                settings: &level_settings,
+                pins: Pins::new(&level_settings),
+                region: Region::new(&level_settings)?,
+            };
+            // the matching so far if the budget ran out, `None` if cancelled
+            let mut partial = None;
+            let solved = solve_exact(
+                &weights,
+                weights.longest_side(),
+                &mut budget,
+                |budget, root, xy| {
+                    if budget.cancelled() {
+                        return false;
+                    }
+                    if budget.tick() {
+                        partial = Some(xy.to_vec());
+                        return false;
+                    }
+
+                    tx.send(ProgressMsg::Progress(progress(root as f32 / n as f32)));
+                    let data = make_new_img(
+                        &source_pixels,
+                        &xy.iter().map(|a| a.unwrap_or(0)).collect::<Vec<_>>(),
+                    );
+                    tx.send(ProgressMsg::UpdatePreview {
+                        width,
+                        height,
+                        data,
+                    });
+                    true
+                },
+            )
+            .await;
+            assignments = solved
+                .or_else(|| partial.map(|xy| complete_assignment(&xy)))
+                .ok_or(CalculateError::Cancelled)?;
+        } else {
+            let (cw, ch) = settings.grid_at(levels[i - 1]);
+            let mut start = upsample(
+                &assignments,
+                (cw as usize, ch as usize),
+                (width as usize, height as usize),
+            );
+            // a coarse cell straddling the region's edge drags outside cells along
+            Region::new(&level_settings)?.confine(&mut start);
+            let mut pixels = make_pixels(&start, &source, &target, &weights, &level_settings);
+            run_generations(
+                &mut pixels,
+                &source_pixels,
+                &target,
+                &weights,
+                &level_settings,
+                REFINE_DIST.min(sidelen),
+                progress,
+                tx,
+                &mut budget,
+            )
+            .await?;
+            assignments = pixel_assignments(&pixels, width);
+        }
+
+        done_cells += cells;
+    }
+
+    Ok(Solved {
+        source_pixels,
+        assignments,
+    })
+}
+
+/// Settings for solving at a lower resolution. A displacement of `d` cells becomes `d / scale`
+/// cells on the coarse grid, so proximity importance grows to keep the spatial term as heavy
+/// relative to colour as it is at full resolution, and the maximum displacement shrinks with it
+/// (rounded down so the upsampled moves still fit).
+fn level_settings(settings: &GenerationSettings, sidelen: u32) -> GenerationSettings {
+    let mut level = settings.clone();
+    let scale = settings.sidelen as f32 / sidelen as f32;
+    level.proximity_importance = settings
+        .cost()
+        .rescale(settings.proximity_importance, scale);
+    level.max_displacement = settings.max_displacement.map(|d| (d as f32 / scale) as u32);
+    level.sidelen = sidelen;
+    level
+}
+
+/// Carries a mapping over to a finer grid by scaling every cell's displacement, then hands each
+/// cell that collides the free source nearest to the one it was after, so the result is a
+/// permutation again.
+/// Both grids are given as cells across and down.
+fn upsample(
+    assignments: &[usize],
+    (cw, ch): (usize, usize),
+    (fw, fh): (usize, usize),
+) -> Vec<usize> {
+    let mut taken = vec![false; fw * fh];
+    let mut result = vec![usize::MAX; fw * fh];
+    let mut collided = vec![];
+    for (target, slot) in result.iter_mut().enumerate() {
+        let (x, y) = (target % fw, target / fw);
+        let (cx, cy) = (x * cw / fw, y * ch / fh);
+        let src = assignments[cy * cw + cx];
+        let (sx, sy) = (src % cw, src / cw);
+        // same offset inside the block, moved to where the coarse cell went
+        let fx = (sx * fw / cw + x - cx * fw / cw).min(fw - 1);
+        let fy = (sy * fh / ch + y - cy * fh / ch).min(fh - 1);
+        let proposed = fy * fw + fx;
+        if !taken[proposed] {
+            taken[proposed] = true;
+            *slot = proposed;
+        } else {
+            collided.push((target, proposed));
+        }
+    }
+
+    for (target, proposed) in collided {
+        let src = region::nearest((fw, fh), proposed, |src| !taken[src]);
+        taken[src] = true;
+        result[target] = src;
+    }
+    result
+}
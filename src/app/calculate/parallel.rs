@@ -0,0 +1,316 @@
+use std::num::NonZeroUsize;
+use std::ops::{Index, IndexMut};
+use std::sync::mpsc;
+use std::{panic, thread};
+
+// below this the copying in and out of tiles costs more than the swaps inside them
+const MIN_TILE_SIDE: usize = 8;
+
+/// Half-open rectangle of cells, `x0..x1` by `y0..y1`.
+#[derive(Clone, Copy)]
+pub(super) struct Tile {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Tile {
+    pub(super) fn cells(&self) -> usize {
+        (self.x1 - self.x0) * (self.y1 - self.y0)
+    }
+
+    /// A random cell of the tile.
+    pub(super) fn cell(&self, rng: &mut frand::Rand, width: usize) -> usize {
+        let x = rng.gen_range(self.x0 as u64..self.x1 as u64) as usize;
+        let y = rng.gen_range(self.y0 as u64..self.y1 as u64) as usize;
+        y * width + x
+    }
+
+    /// A random cell at most `max_dist` cells away from `apos` on each axis, clamped into the
+    /// tile.
+    pub(super) fn partner(
+        &self,
+        rng: &mut frand::Rand,
+        apos: usize,
+        max_dist: u32,
+        width: usize,
+    ) -> usize {
+        let max_dist = max_dist as i64;
+        let (ax, ay) = super::cell_pos(apos, width);
+        let bx = (ax as i64 + rng.gen_range(-max_dist..max_dist + 1))
+            .clamp(self.x0 as i64, self.x1 as i64 - 1);
+        let by = (ay as i64 + rng.gen_range(-max_dist..max_dist + 1))
+            .clamp(self.y0 as i64, self.y1 as i64 - 1);
+        by as usize * width + bx as usize
+    }
+
+    /// A random cell of the tile and a partner for it, see `partner`.
+    pub(super) fn pick(
+        &self,
+        rng: &mut frand::Rand,
+        max_dist: u32,
+        width: usize,
+    ) -> (usize, usize) {
+        let apos = self.cell(rng, width);
+        (apos, self.partner(rng, apos, max_dist, width))
+    }
+}
+
+/// Runs swap generations on every core. Each generation cuts the grid into tiles at a random
+/// offset and colours them like a checkerboard with four colours, so two tiles of the same colour
+/// are always at least a tile apart. The four colours run one after another, with all tiles of a
+/// colour worked on at once.
+///
+/// Every tile gets its own generator seeded from the generation's seed and the tile, so the result
+/// only depends on the seed and not on the number of threads or how they are scheduled.
+pub(super) struct Tiled<P> {
+    width: usize,
+    height: usize,
+    // one per tile of the colour being worked on, holding the tile and the ring around it
+    buffers: Vec<Vec<P>>,
+    // started once and fed every colour of every generation, until this is dropped
+    workers: Vec<Worker>,
+    done: mpsc::Receiver<thread::Result<usize>>,
+}
+
+/// A worker thread and where to send it jobs.
+struct Worker {
+    jobs: mpsc::Sender<Job>,
+    handle: thread::JoinHandle<()>,
+}
+
+// swaps made by the tiles of one worker
+type Job = Box<dyn FnOnce() -> usize + Send>;
+
+impl<P: Copy + Send + Sync> Tiled<P> {
+    pub(super) fn new(width: usize, height: usize) -> Self {
+        let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let (done_tx, done) = mpsc::channel();
+        let workers = (0..threads)
+            .map(|_| {
+                let (jobs, rx) = mpsc::channel::<Job>();
+                let done = done_tx.clone();
+                let handle = thread::spawn(move || {
+                    for job in rx {
+                        // a panic is passed on by `generation`, which waits for every job first
+                        let made = panic::catch_unwind(panic::AssertUnwindSafe(job));
+                        if done.send(made).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Worker { jobs, handle }
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            buffers: vec![],
+            workers,
+            done,
+        }
+    }
+
+    /// One generation over `pixels` with tiles about `tile_side` cells wide. `work` swaps cells
+    /// inside the tile it's given and returns how many swaps it made. It may read the cells right
+    /// next to the tile but must not change anything outside of it. Returns the swaps made.
+    pub(super) fn generation<F>(
+        &mut self,
+        pixels: &mut [P],
+        tile_side: usize,
+        seed: u64,
+        work: F,
+    ) -> usize
+    where
+        F: Fn(&mut TileCells<'_, P>, Tile, &mut frand::Rand) -> usize + Sync,
+    {
+        let (width, height) = (self.width, self.height);
+        let longest = width.max(height);
+        let tile_side = tile_side.clamp(MIN_TILE_SIDE.min(longest), longest);
+        let mut rng = frand::Rand::with_seed(seed);
+        let cols = spans(
+            width,
+            tile_side,
+            rng.gen_range(0..tile_side as u64) as usize,
+        );
+        let rows = spans(
+            height,
+            tile_side,
+            rng.gen_range(0..tile_side as u64) as usize,
+        );
+
+        let mut swaps_made = 0;
+        for phase in 0..4 {
+            let tiles = rows
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| j % 2 == phase / 2)
+                .flat_map(|(_, &(y0, y1))| {
+                    cols.iter()
+                        .enumerate()
+                        .filter(|(i, _)| i % 2 == phase % 2)
+                        .map(move |(_, &(x0, x1))| Tile { x0, y0, x1, y1 })
+                })
+                .collect::<Vec<_>>();
+            let threads = self.workers.len().min(tiles.len());
+            if threads == 0 {
+                continue;
+            }
+            if self.buffers.len() < tiles.len() {
+                self.buffers.resize_with(tiles.len(), Vec::new);
+            }
+
+            // every worker takes every `threads`th tile, with its buffer
+            let mut batches = (0..threads).map(|_| vec![]).collect::<Vec<_>>();
+            for (k, (tile, buffer)) in tiles.iter().zip(&mut self.buffers).enumerate() {
+                batches[k % threads].push((k, *tile, buffer));
+            }
+            let shared = &*pixels;
+            let work = &work;
+            let mut sent = 0;
+            for (worker, batch) in self.workers.iter().zip(batches) {
+                let job = move || {
+                    let mut made = 0;
+                    for (k, tile, buffer) in batch {
+                        let mut cells = TileCells::copy(shared, buffer, tile, width, height);
+                        let mut rng = frand::Rand::with_seed(
+                            seed ^ ((((k as u64) << 2) | phase as u64) + 1)
+                                .wrapping_mul(0x9E37_79B9_7F4A_7C15),
+                        );
+                        made += work(&mut cells, tile, &mut rng);
+                    }
+                    made
+                };
+                let job: Box<dyn FnOnce() -> usize + Send + '_> = Box::new(job);
+                // SAFETY: the job borrows `pixels`, `work` and the buffers, which outlive it: every
+                // job that was sent is waited for below before any of them can be touched again,
+                // and a job that panics still reports back
+                let job: Job = unsafe { std::mem::transmute(job) };
+                if worker.jobs.send(job).is_ok() {
+                    sent += 1;
+                }
+            }
+            let mut panicked = None;
+            for _ in 0..sent {
+                match self.done.recv() {
+                    Ok(Ok(made)) => swaps_made += made,
+                    Ok(Err(payload)) => panicked = Some(payload),
+                    Err(_) => break,
+                }
+            }
+            if let Some(payload) = panicked {
+                panic::resume_unwind(payload);
+            }
+            assert_eq!(sent, threads, "a tile worker stopped");
+
+            for (tile, buffer) in tiles.iter().zip(&self.buffers) {
+                let rect = grow(*tile, width, height);
+                let rect_width = rect.x1 - rect.x0;
+                for y in tile.y0..tile.y1 {
+                    let from = (y - rect.y0) * rect_width + tile.x0 - rect.x0;
+                    let to = y * width + tile.x0;
+                    let len = tile.x1 - tile.x0;
+                    pixels[to..to + len].copy_from_slice(&buffer[from..from + len]);
+                }
+            }
+        }
+        swaps_made
+    }
+}
+
+impl<P> Drop for Tiled<P> {
+    fn drop(&mut self) {
+        for Worker { jobs, handle } in self.workers.drain(..) {
+            // a closed channel ends the worker's loop
+            drop(jobs);
+            handle.join().ok();
+        }
+    }
+}
+
+/// A tile and the ring of cells around it, indexed by cell like the whole grid. Reading outside
+/// of that or writing outside the tile is a bug in the caller.
+pub(super) struct TileCells<'a, P> {
+    cells: &'a mut [P],
+    // the tile plus its ring
+    rect: Tile,
+    width: usize,
+}
+
+impl<'a, P: Copy> TileCells<'a, P> {
+    /// The whole grid, for running the same swaps without tiles.
+    pub(super) fn whole(cells: &'a mut [P], width: usize, height: usize) -> Self {
+        let rect = Tile {
+            x0: 0,
+            y0: 0,
+            x1: width,
+            y1: height,
+        };
+        Self { cells, rect, width }
+    }
+
+    /// `tile` and its ring copied out of the whole grid `from` into `buffer`.
+    fn copy(from: &[P], buffer: &'a mut Vec<P>, tile: Tile, width: usize, height: usize) -> Self {
+        let rect = grow(tile, width, height);
+        buffer.clear();
+        for y in rect.y0..rect.y1 {
+            buffer.extend_from_slice(&from[y * width + rect.x0..y * width + rect.x1]);
+        }
+        Self {
+            cells: buffer,
+            rect,
+            width,
+        }
+    }
+
+    fn local(&self, cell: usize) -> usize {
+        let (x, y) = (cell % self.width, cell / self.width);
+        let rect = self.rect;
+        debug_assert!(
+            (rect.x0..rect.x1).contains(&x) && (rect.y0..rect.y1).contains(&y),
+            "cell {cell} is outside the tile"
+        );
+        (y - rect.y0) * (rect.x1 - rect.x0) + x - rect.x0
+    }
+}
+
+impl<P: Copy> Index<usize> for TileCells<'_, P> {
+    type Output = P;
+
+    fn index(&self, cell: usize) -> &P {
+        &self.cells[self.local(cell)]
+    }
+}
+
+impl<P: Copy> IndexMut<usize> for TileCells<'_, P> {
+    fn index_mut(&mut self, cell: usize) -> &mut P {
+        let local = self.local(cell);
+        &mut self.cells[local]
+    }
+}
+
+impl<P: Copy> super::Cells<P> for TileCells<'_, P> {}
+
+/// Cuts `0..len` into spans of `side`, the first one shortened by `offset`.
+fn spans(len: usize, side: usize, offset: usize) -> Vec<(usize, usize)> {
+    let mut spans = vec![];
+    let mut start = 0;
+    let mut end = side - offset;
+    while start < len {
+        spans.push((start, end.min(len)));
+        start = end;
+        end += side;
+    }
+    spans
+}
+
+/// The tile plus the ring of cells around it.
+fn grow(tile: Tile, width: usize, height: usize) -> Tile {
+    Tile {
+        x0: tile.x0.saturating_sub(1),
+        y0: tile.y0.saturating_sub(1),
+        x1: (tile.x1 + 1).min(width),
+        y1: (tile.y1 + 1).min(height),
+    }
+}
@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+use crate::app::calculate::util::GenerationSettings;
+
+// cells within this fraction of the grid's longer side from a source pin follow it, less the
+// further out
+const PIN_RADIUS: f32 = 0.06;
+
+/// A source point that should end up on a target point, both as fractions (0..1) of the cropped
+/// source and target.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pin {
+    pub source: (f32, f32),
+    pub target: (f32, f32),
+}
+
+/// `GenerationSettings::pins` on one grid, as an extra cost every solver adds to the appearance
+/// and proximity cost. A source cell near a pin pays for every squared cell it lands away from
+/// where the pin's offset would take it. Right on the pin a single cell off costs as much as the
+/// worst appearance mismatch, which makes the pinned cells themselves all but fixed.
+pub(crate) struct Pins {
+    // (source x, source y, offset x, offset y) in cells
+    pins: Vec<(f32, f32, f32, f32)>,
+    radius: f32,
+    strength: f32,
+}
+
+impl Pins {
+    pub fn new(settings: &GenerationSettings) -> Self {
+        let (w, h) = settings.grid();
+        let (w, h) = (w as f32, h as f32);
+        let to_cells = |(x, y): (f32, f32)| {
+            (
+                (x.clamp(0.0, 1.0) * w).min(w - 1.0),
+                (y.clamp(0.0, 1.0) * h).min(h - 1.0),
+            )
+        };
+        Self {
+            pins: settings
+                .pins
+                .iter()
+                .map(|pin| {
+                    let (sx, sy) = to_cells(pin.source);
+                    let (tx, ty) = to_cells(pin.target);
+                    (sx, sy, tx - sx, ty - sy)
+                })
+                .collect(),
+            radius: (PIN_RADIUS * w.max(h)).max(1.5),
+            strength: (settings.cost().appearance.max_difference() * 255) as f32,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty()
+    }
+
+    /// Extra cost of sending the source cell at `source` to the target cell at `target`.
+    #[inline(always)]
+    pub fn cost(&self, source: (u32, u32), target: (u32, u32)) -> i64 {
+        let mut sum = 0.0;
+        for &(sx, sy, ox, oy) in &self.pins {
+            let (x, y) = (source.0 as f32, source.1 as f32);
+            let d = ((x - sx).powi(2) + (y - sy).powi(2)).sqrt();
+            if d >= self.radius {
+                continue;
+            }
+            let influence = 1.0 - d / self.radius;
+            let miss = (target.0 as f32 - (x + ox)).powi(2) + (target.1 as f32 - (y + oy)).powi(2);
+            sum += influence * influence * self.strength * miss;
+        }
+        sum as i64
+    }
+
+    /// Whether pin number `pin` pulls the source cell at `cell` along at all.
+    #[inline(always)]
+    pub fn pulls(&self, pin: usize, cell: (i64, i64)) -> bool {
+        let (sx, sy, _, _) = self.pins[pin];
+        (cell.0 as f32 - sx).powi(2) + (cell.1 as f32 - sy).powi(2) < self.radius * self.radius
+    }
+
+    /// Windowed solvers only look at the cells within `radius` of a cell, but a pin takes the
+    /// cells near its source much further. So they also look at a window around each of these
+    /// centres: where a pin takes `cell` if it pulls it, and where the cells a pin brings to
+    /// `cell` start from, which only count if the pin pulls them (the pin's index comes along
+    /// for `pulls`). A pair found this way is found from either end, so windows stay symmetric.
+    pub fn window_centres(
+        &self,
+        cell: (u32, u32),
+        radius: usize,
+    ) -> impl Iterator<Item = ((i64, i64), Option<usize>)> + '_ {
+        let (x, y) = (cell.0 as i64, cell.1 as i64);
+        let reach = radius as f32 + self.radius;
+        self.pins
+            .iter()
+            .enumerate()
+            .flat_map(move |(pin, &(sx, sy, ox, oy))| {
+                let (ox, oy) = (ox.round() as i64, oy.round() as i64);
+                let taken_to = self.pulls(pin, (x, y)).then_some(((x + ox, y + oy), None));
+                let (fx, fy) = (x - ox, y - oy);
+                let brought_from = ((fx as f32 - sx).abs() <= reach
+                    && (fy as f32 - sy).abs() <= reach)
+                    .then_some(((fx, fy), Some(pin)));
+                taken_to.into_iter().chain(brought_from)
+            })
+    }
+}
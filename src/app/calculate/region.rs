@@ -0,0 +1,260 @@
+use crate::app::calculate::cell_pos;
+use crate::app::calculate::error::CalculateError;
+use crate::app::calculate::util::GenerationSettings;
+
+/// `GenerationSettings::mask` and `max_displacement` on one grid. Cells outside the region keep
+/// their own source cell and the solvers only move the ones inside, among themselves and no
+/// further than the maximum displacement.
+pub(crate) struct Region {
+    width: usize,
+    height: usize,
+    max_displacement: Option<usize>,
+    // `None` when every cell is inside
+    inside: Option<Vec<bool>>,
+    // the inside cells in order, only filled in with a mask
+    cells: Vec<usize>,
+    // half-open bounding box of the inside cells, x0, y0, x1, y1
+    bounds: (usize, usize, usize, usize),
+}
+
+impl Region {
+    pub fn new(settings: &GenerationSettings) -> Result<Self, CalculateError> {
+        let grid = settings.grid();
+        let (width, height) = (grid.0 as usize, grid.1 as usize);
+        let max_displacement = settings.max_displacement.map(|d| d as usize);
+        let Some(inside) = settings.mask_at(grid)? else {
+            return Ok(Self {
+                width,
+                height,
+                max_displacement,
+                inside: None,
+                cells: vec![],
+                bounds: (0, 0, width, height),
+            });
+        };
+        let cells = (0..inside.len())
+            .filter(|&cell| inside[cell])
+            .collect::<Vec<_>>();
+        let mut bounds = (width, height, 0, 0);
+        for &cell in &cells {
+            let (x, y) = (cell % width, cell / width);
+            bounds = (
+                bounds.0.min(x),
+                bounds.1.min(y),
+                bounds.2.max(x + 1),
+                bounds.3.max(y + 1),
+            );
+        }
+        Ok(Self {
+            width,
+            height,
+            max_displacement,
+            inside: Some(inside),
+            cells,
+            bounds,
+        })
+    }
+
+    /// Whether every cell morphs.
+    pub fn whole(&self) -> bool {
+        self.inside.is_none()
+    }
+
+    #[inline(always)]
+    pub fn contains(&self, cell: usize) -> bool {
+        self.inside.as_ref().is_none_or(|inside| inside[cell])
+    }
+
+    /// How far (chebyshev, in cells) any cell may travel, the grid's longer side without a limit.
+    pub fn reach(&self) -> usize {
+        let longest = self.width.max(self.height);
+        self.max_displacement.map_or(longest, |d| d.min(longest))
+    }
+
+    /// Whether a cell at `from` may travel to `to`, coordinates in cells.
+    #[inline(always)]
+    pub fn within_reach(&self, from: (u32, u32), to: (u32, u32)) -> bool {
+        self.max_displacement.is_none_or(|d| {
+            let dx = from.0 as i64 - to.0 as i64;
+            let dy = from.1 as i64 - to.1 as i64;
+            dx * dx + dy * dy <= (d * d) as i64
+        })
+    }
+
+    /// Whether target cell `row` may take source cell `col`.
+    #[inline(always)]
+    pub fn allows(&self, row: usize, col: usize) -> bool {
+        self.contains(row)
+            && self.contains(col)
+            && (self.max_displacement.is_none()
+                || self.within_reach(cell_pos(row, self.width), cell_pos(col, self.width)))
+    }
+
+    /// How many of the grid's `n` cells are inside.
+    pub fn len(&self, n: usize) -> usize {
+        if self.whole() { n } else { self.cells.len() }
+    }
+
+    /// The inside cells of a grid of `n` cells, in order.
+    pub fn cells(&self, n: usize) -> impl Iterator<Item = usize> + '_ {
+        let (all, listed) = match &self.inside {
+            None => (0..n, &[][..]),
+            Some(_) => (0..0, &self.cells[..]),
+        };
+        all.chain(listed.iter().copied())
+    }
+
+    /// A random inside cell of a grid of `n` cells, `None` if the region is empty.
+    pub fn random_cell(&self, rng: &mut frand::Rand, n: usize) -> Option<usize> {
+        let len = self.len(n);
+        if len == 0 {
+            return None;
+        }
+        let i = rng.gen_range(0..len as u64) as usize;
+        Some(if self.whole() { i } else { self.cells[i] })
+    }
+
+    /// Clamps a cell's coordinates into the bounding box of the region, so swap partners are
+    /// mostly picked where they can actually move.
+    pub fn clamp(&self, x: i64, y: i64) -> (usize, usize) {
+        let (x0, y0, x1, y1) = self.bounds;
+        if x1 <= x0 || y1 <= y0 {
+            return (x.max(0) as usize, y.max(0) as usize);
+        }
+        (
+            x.clamp(x0 as i64, x1 as i64 - 1) as usize,
+            y.clamp(y0 as i64, y1 as i64 - 1) as usize,
+        )
+    }
+
+    /// Whether a maximum displacement is set.
+    pub fn limited(&self) -> bool {
+        self.max_displacement.is_some()
+    }
+
+    /// Makes `assignments` only use moves the region allows, and returns how many cells it had
+    /// to give another source. Cells outside keep their own source, and the inside cells that
+    /// lose theirs to this get the nearest inside source nobody has. Then `close_cycles`
+    /// breaks the moves longer than the maximum displacement out of their cycles, so the moves
+    /// around them that were fine mostly stay as they are.
+    pub fn confine(&self, assignments: &mut [usize]) -> usize {
+        let before = assignments.to_vec();
+        if let Some(inside) = &self.inside {
+            self.confine_to_mask(inside, assignments);
+        }
+        if self.max_displacement.is_some() {
+            self.close_cycles(assignments);
+        }
+        before
+            .iter()
+            .zip(assignments.iter())
+            .filter(|(a, b)| a != b)
+            .count()
+    }
+
+    #[inline(always)]
+    fn reaches(&self, row: usize, col: usize) -> bool {
+        self.within_reach(cell_pos(row, self.width), cell_pos(col, self.width))
+    }
+
+    /// Cuts every cycle of the permutation at its moves longer than the maximum displacement
+    /// and closes each piece on itself: the last of its cells that may take the piece's first
+    /// source does, and the cells after that one keep their own.
+    fn close_cycles(&self, assignments: &mut [usize]) {
+        let mut visited = vec![false; assignments.len()];
+        let mut cycle = vec![];
+        let mut piece = vec![];
+        for start in 0..assignments.len() {
+            // cycle[i] takes cycle[i + 1]'s source, the last one the first one's
+            cycle.clear();
+            let mut cell = start;
+            while !visited[cell] {
+                visited[cell] = true;
+                cycle.push(cell);
+                cell = assignments[cell];
+            }
+            let len = cycle.len();
+            let Some(last_cut) = (0..len).rfind(|&i| !self.reaches(cycle[i], cycle[(i + 1) % len]))
+            else {
+                continue;
+            };
+            // pieces run from just after one cut to the next, starting after the last one
+            for i in (last_cut + 1..len).chain(0..=last_cut) {
+                piece.push(cycle[i]);
+                if self.reaches(cycle[i], cycle[(i + 1) % len]) {
+                    continue;
+                }
+                let first = piece[0];
+                // the first cell taking its own source always works
+                let closing = (0..piece.len())
+                    .rfind(|&j| self.reaches(piece[j], first))
+                    .unwrap_or(0);
+                assignments[piece[closing]] = first;
+                for &cell in &piece[closing + 1..] {
+                    assignments[cell] = cell;
+                }
+                piece.clear();
+            }
+        }
+    }
+
+    fn confine_to_mask(&self, inside: &[bool], assignments: &mut [usize]) {
+        let mut taken = vec![false; assignments.len()];
+        for (cell, src) in assignments.iter_mut().enumerate() {
+            if !inside[cell] {
+                *src = cell;
+                taken[cell] = true;
+            }
+        }
+        let mut lost = vec![];
+        for (cell, &src) in assignments.iter().enumerate() {
+            if inside[cell] {
+                if inside[src] && !taken[src] {
+                    taken[src] = true;
+                } else {
+                    lost.push(cell);
+                }
+            }
+        }
+        for cell in lost {
+            let src = nearest((self.width, self.height), cell, |src| {
+                inside[src] && !taken[src]
+            });
+            assignments[cell] = src;
+            taken[src] = true;
+        }
+    }
+}
+
+/// The cell of a `width`×`height` grid nearest to `cell` (euclidean) that is `free`, looking in
+/// growing squares around it until no closer one can turn up. There has to be one.
+pub(super) fn nearest(
+    (width, height): (usize, usize),
+    cell: usize,
+    free: impl Fn(usize) -> bool,
+) -> usize {
+    let (x, y) = (cell % width, cell / width);
+    let mut best: Option<(usize, usize)> = None;
+    for r in 0..width.max(height) {
+        // everything further out is at least `r` away
+        if best.is_some_and(|(d, _)| d < r * r) {
+            break;
+        }
+        let (x0, x1) = (x as i64 - r as i64, x as i64 + r as i64);
+        for cy in y.saturating_sub(r)..(y + r + 1).min(height) {
+            // the square's edge: whole rows at the top and bottom, both ends of the others
+            let step = if y.abs_diff(cy) == r { 1 } else { 2 * r };
+            for cx in (x0..=x1).step_by(step) {
+                if cx < 0 || cx >= width as i64 {
+                    continue;
+                }
+                let src = cy * width + cx as usize;
+                let d = x.abs_diff(cx as usize).pow(2) + y.abs_diff(cy).pow(2);
+                if free(src) && best.is_none_or(|(best, _)| d < best) {
+                    best = Some((d, src));
+                }
+            }
+        }
+    }
+    best.unwrap().1
+}
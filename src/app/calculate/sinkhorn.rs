@@ -0,0 +1,279 @@
+use std::sync::Arc;
+
+use crate::app::calculate::error::CalculateError;
+use crate::app::calculate::pins::Pins;
+use crate::app::calculate::region::Region;
+use crate::app::calculate::util::JobControl;
+use crate::app::calculate::util::{Budget, GenerationSettings, ProgressSink};
+use crate::app::calculate::{
+    ImgDiffWeights, ProgressMsg, REFINE_DIST, Solved, Weights, make_new_img, make_pixels,
+    pixel_assignments, run_generations, util,
+};
+use crate::app::preset::UnprocessedPreset;
+
+// each stage divides the blur by this until it reaches the requested epsilon
+const EPSILON_SCALING: f64 = 4.0;
+const MAX_STAGE_ITERATIONS: usize = 60;
+// a stage is done once no source cell is over- or under-used by more than this
+const TOLERANCE: f64 = 1e-2;
+// plan entries per row considered when rounding to a permutation
+const ROUND_CANDIDATES: usize = 8;
+const ITERATIONS_PER_UPDATE: usize = 5;
+// share of the progress bar for the Sinkhorn stages, the swap refinement gets the rest
+const STAGES_PROGRESS: f32 = 0.8;
+
+/// Log-domain Sinkhorn iterations on the windowed cost matrix. Costs are divided by the largest
+/// spread inside a window, so epsilon is a blur relative to that and doesn't depend on the images.
+struct Sinkhorn<'w, 'a> {
+    weights: &'w ImgDiffWeights<'a>,
+    radius: usize,
+    scale: f64,
+    // dual potentials for target rows and source columns
+    f: Vec<f64>,
+    g: Vec<f64>,
+}
+
+impl Sinkhorn<'_, '_> {
+    fn cost(&self, row: usize, col: usize) -> f64 {
+        -self.weights.at(row, col) as f64 / self.scale
+    }
+
+    /// Makes every target row inside the region receive exactly one unit of mass. Windows only
+    /// hold cells inside it too, so the cells outside never take part.
+    fn update_rows(&mut self, eps: f64) {
+        let weights = self.weights;
+        for row in weights.region.cells(self.f.len()) {
+            let lse = log_sum_exp(
+                self.weights
+                    .window(row, self.radius)
+                    .map(|col| (self.g[col] - self.cost(row, col)) / eps),
+            );
+            self.f[row] = -eps * lse;
+        }
+    }
+
+    /// Makes every source column send exactly one unit of mass, and returns how far off the
+    /// worst column was before. Windows are symmetric, so a column's rows are its own window.
+    fn update_cols(&mut self, eps: f64) -> f64 {
+        let mut error: f64 = 0.0;
+        let weights = self.weights;
+        for col in weights.region.cells(self.g.len()) {
+            let lse = log_sum_exp(
+                self.weights
+                    .window(col, self.radius)
+                    .map(|row| (self.f[row] - self.cost(row, col)) / eps),
+            );
+            let g = -eps * lse;
+            // the column's mass was exp((g_old - g_new) / eps)
+            error = error.max(((self.g[col] - g) / eps).exp_m1().abs());
+            self.g[col] = g;
+        }
+        error
+    }
+
+    /// Every row's heaviest plan entry, for previews. Not a permutation, sources can repeat, but
+    /// it only costs one pass over the windows instead of a full rounding.
+    fn preview(&self) -> Vec<usize> {
+        let n = self.f.len();
+        (0..n)
+            .map(|row| {
+                if !self.weights.region.contains(row) {
+                    return row;
+                }
+                self.weights
+                    .window(row, self.radius)
+                    .max_by(|&a, &b| {
+                        (self.g[a] - self.cost(row, a)).total_cmp(&(self.g[b] - self.cost(row, b)))
+                    })
+                    .unwrap_or(row)
+            })
+            .collect()
+    }
+
+    /// Rounds the current plan to a permutation by handing out the heaviest entries first. Rows
+    /// left without any of their candidates take the cheapest source cell nobody claimed in their
+    /// window, or anywhere if it's all claimed, and rows outside the region keep their own.
+    fn round(&self) -> Vec<usize> {
+        let n = self.f.len();
+        let mut entries = Vec::with_capacity(n * ROUND_CANDIDATES);
+        for row in self.weights.region.cells(n) {
+            let mut top = [(f64::NEG_INFINITY, usize::MAX); ROUND_CANDIDATES];
+            for col in self.weights.window(row, self.radius) {
+                // log of the plan entry, times epsilon, minus the row's potential
+                let value = self.g[col] - self.cost(row, col);
+                if value > top[ROUND_CANDIDATES - 1].0 {
+                    let mut i = ROUND_CANDIDATES - 1;
+                    while i > 0 && top[i - 1].0 < value {
+                        top[i] = top[i - 1];
+                        i -= 1;
+                    }
+                    top[i] = (value, col);
+                }
+            }
+            entries.extend(
+                top.iter()
+                    .filter(|t| t.1 != usize::MAX)
+                    .map(|&(value, col)| (value + self.f[row], row, col)),
+            );
+        }
+        entries.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut assignments = vec![usize::MAX; n];
+        let mut taken = vec![false; n];
+        for cell in (0..n).filter(|&cell| !self.weights.region.contains(cell)) {
+            assignments[cell] = cell;
+            taken[cell] = true;
+        }
+        for (_, row, col) in entries {
+            if assignments[row] == usize::MAX && !taken[col] {
+                assignments[row] = col;
+                taken[col] = true;
+            }
+        }
+
+        for (row, slot) in assignments.iter_mut().enumerate() {
+            if *slot != usize::MAX {
+                continue;
+            }
+            let free = |col: &usize| !taken[*col];
+            let col = self
+                .weights
+                .window(row, self.radius)
+                .filter(free)
+                .max_by_key(|&col| self.weights.at(row, col))
+                .or_else(|| {
+                    (0..n)
+                        .filter(free)
+                        .max_by_key(|&col| self.weights.at(row, col))
+                })
+                .unwrap();
+            *slot = col;
+            taken[col] = true;
+        }
+        // a leftover whose window was all taken can be anywhere
+        self.weights.region.confine(&mut assignments);
+        assignments
+    }
+}
+
+fn log_sum_exp(values: impl Iterator<Item = f64>) -> f64 {
+    let mut max = f64::NEG_INFINITY;
+    let mut sum = 0.0;
+    for v in values {
+        if v > max {
+            sum = sum * (max - v).exp() + 1.0;
+            max = v;
+        } else {
+            sum += (v - max).exp();
+        }
+    }
+    max + sum.ln()
+}
+
+/// Entropic optimal transport: Sinkhorn iterations with a shrinking blur produce a soft plan
+/// between target and source cells, which is then rounded to a permutation. Larger
+/// `sinkhorn_epsilon` converges faster but spreads each cell's mass over more candidates.
+pub async fn process_sinkhorn<S: ProgressSink>(
+    unprocessed: UnprocessedPreset,
+    settings: GenerationSettings,
+    tx: &mut S,
+    control: Arc<JobControl>,
+) -> Result<Solved, CalculateError> {
+    let source_img = util::source_image(&unprocessed)?;
+    let util::Images {
+        source_pixels,
+        source,
+        target,
+        weights,
+    } = util::get_images(source_img, &settings)?;
+    let (width, height) = settings.grid();
+
+    let weights = ImgDiffWeights {
+        source,
+        target,
+        weights,
+        width: width as usize,
+        height: height as usize,
+        settings: &settings,
+        pins: Pins::new(&settings),
+        region: Region::new(&settings)?,
+    };
+
+    let n = weights.rows();
+    let radius = weights.solver_radius();
+    let mut sinkhorn = Sinkhorn {
+        weights: &weights,
+        radius,
+        scale: weights.max_spread(radius) as f64,
+        f: vec![0.0; n],
+        g: vec![0.0; n],
+    };
+
+    let target_eps = (settings.sinkhorn_epsilon as f64).max(1e-4);
+    let mut epsilons = vec![];
+    let mut eps = 1.0;
+    while eps > target_eps {
+        epsilons.push(eps);
+        eps /= EPSILON_SCALING;
+    }
+    epsilons.push(target_eps);
+
+    let mut budget = Budget::new(&settings, control);
+    'stages: for (stage, &eps) in epsilons.iter().enumerate() {
+        for iteration in 0..MAX_STAGE_ITERATIONS {
+            sinkhorn.update_rows(eps);
+            let error = sinkhorn.update_cols(eps);
+            if error < TOLERANCE {
+                break;
+            }
+
+            if iteration % ITERATIONS_PER_UPDATE == 0 {
+                budget.pause().await;
+                if budget.cancelled() {
+                    return Err(CalculateError::Cancelled);
+                }
+                if budget.tick() {
+                    break 'stages;
+                }
+
+                let stage_progress = iteration as f32 / MAX_STAGE_ITERATIONS as f32;
+                tx.send(ProgressMsg::Progress(
+                    STAGES_PROGRESS * (stage as f32 + stage_progress) / epsilons.len() as f32,
+                ));
+
+                let data = make_new_img(&source_pixels, &sinkhorn.preview());
+                tx.send(ProgressMsg::UpdatePreview {
+                    width,
+                    height,
+                    data,
+                });
+            }
+        }
+    }
+
+    let rounded = sinkhorn.round();
+    let mut pixels = make_pixels(
+        &rounded,
+        &weights.source,
+        &weights.target,
+        &weights.weights,
+        &settings,
+    );
+    run_generations(
+        &mut pixels,
+        &source_pixels,
+        &weights.target,
+        &weights.weights,
+        &settings,
+        REFINE_DIST.min(settings.sidelen),
+        |p| STAGES_PROGRESS + p * (1.0 - STAGES_PROGRESS),
+        tx,
+        &mut budget,
+    )
+    .await?;
+
+    Ok(Solved {
+        source_pixels,
+        assignments: pixel_assignments(&pixels, width),
+    })
+}
@@ -0,0 +1,204 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::app::calculate::cost::CostModel;
+use crate::app::calculate::util::Budget;
+use crate::app::calculate::{ImgDiffWeights, cell_pos};
+
+/// Min-cost assignment over the sparse graph where each row only sees the columns within
+/// `radius` of itself and those a pin brings to it. Rows are matched one at a time along
+/// shortest augmenting paths (Dijkstra on reduced costs, stopping at the first free column), so
+/// the result is exact for that graph, though not necessarily for the full one.
+/// A row whose search runs out of reachable free columns falls back to seeing every column.
+/// Cells outside the region are matched to themselves up front and left out of the graph, as
+/// are moves further than it allows. The identity is always left, so every row finds a match.
+///
+/// Every 100 augmentations it awaits `budget.pause()`, then `on_progress` gets the partial
+/// matching and returns false to abort.
+pub(super) async fn sparse_hungarian(
+    weights: &ImgDiffWeights<'_>,
+    radius: usize,
+    budget: &mut Budget,
+    mut on_progress: impl FnMut(&mut Budget, usize, &[Option<usize>]) -> bool,
+) -> Option<Vec<usize>> {
+    let n = weights.target.len();
+    let mut graph = SparseGraph {
+        weights,
+        radius,
+        dense: vec![false; n],
+    };
+
+    // column potentials, row potentials are implied by the matched edge being tight
+    let mut v = vec![0i64; n];
+    let mut row_col: Vec<Option<usize>> = vec![None; n];
+    let mut col_row: Vec<Option<usize>> = vec![None; n];
+
+    // with all potentials at zero every row's cheapest edge is tight, so take those that are free
+    let mut free_rows = vec![];
+    for (row, matched) in row_col.iter_mut().enumerate() {
+        if !weights.region.contains(row) {
+            col_row[row] = Some(row);
+            *matched = Some(row);
+            continue;
+        }
+        let mut best = (i64::MAX, 0);
+        graph.for_each_edge(row, |col, cost| best = best.min((cost, col)));
+        if col_row[best.1].is_none() {
+            col_row[best.1] = Some(row);
+            *matched = Some(best.1);
+        } else {
+            free_rows.push(row);
+        }
+    }
+
+    let mut dist = vec![0i64; n];
+    let mut pred = vec![0usize; n];
+    let mut seen = vec![0u32; n];
+    let mut scanned = vec![0u32; n];
+    let mut scanned_cols = vec![];
+    let mut heap = BinaryHeap::new();
+    let mut stamp = 0;
+
+    let mut matched = n - free_rows.len();
+    for (i, &root) in free_rows.iter().enumerate() {
+        let end = loop {
+            stamp += 1;
+            heap.clear();
+            scanned_cols.clear();
+
+            graph.for_each_edge(root, |col, cost| {
+                let d = cost - v[col];
+                if seen[col] != stamp || d < dist[col] {
+                    seen[col] = stamp;
+                    dist[col] = d;
+                    pred[col] = root;
+                    heap.push(Reverse((d, col)));
+                }
+            });
+
+            let mut end = None;
+            while let Some(Reverse((d, col))) = heap.pop() {
+                if scanned[col] == stamp || d > dist[col] {
+                    continue;
+                }
+                let Some(row) = col_row[col] else {
+                    end = Some(col);
+                    break;
+                };
+                scanned[col] = stamp;
+                scanned_cols.push(col);
+
+                // reduced cost of leaving `row`'s current (tight) edge
+                let u = graph.cost(row, col) - v[col];
+                graph.for_each_edge(row, |next, cost| {
+                    if scanned[next] == stamp {
+                        return;
+                    }
+                    let nd = d + cost - v[next] - u;
+                    if seen[next] != stamp || nd < dist[next] {
+                        seen[next] = stamp;
+                        dist[next] = nd;
+                        pred[next] = row;
+                        heap.push(Reverse((nd, next)));
+                    }
+                });
+            }
+
+            match end {
+                Some(col) => break col,
+                // everything reachable is taken, so let this row reach everything
+                None => graph.dense[root] = true,
+            }
+        };
+
+        let d_end = dist[end];
+        for &col in &scanned_cols {
+            v[col] += dist[col] - d_end;
+        }
+
+        let mut col = end;
+        loop {
+            let row = pred[col];
+            let prev = row_col[row];
+            row_col[row] = Some(col);
+            col_row[col] = Some(row);
+            match prev {
+                Some(prev) => col = prev,
+                None => break,
+            }
+        }
+
+        matched += 1;
+        if i % 100 == 0 {
+            budget.pause().await;
+            if !on_progress(budget, matched, &row_col) {
+                return None;
+            }
+        }
+    }
+
+    Some(row_col.into_iter().map(Option::unwrap).collect())
+}
+
+struct SparseGraph<'w, 'a> {
+    weights: &'w ImgDiffWeights<'a>,
+    radius: usize,
+    dense: Vec<bool>,
+}
+
+impl SparseGraph<'_, '_> {
+    fn cost(&self, row: usize, col: usize) -> i64 {
+        let width = self.weights.width;
+        self.weights.settings.cost().cost(
+            cell_pos(row, width),
+            cell_pos(col, width),
+            self.weights.target[row],
+            self.weights.source[col],
+            self.weights.weights[row],
+            self.weights.settings.proximity_importance,
+        ) + self
+            .weights
+            .pins
+            .cost(cell_pos(col, width), cell_pos(row, width))
+    }
+
+    fn for_each_edge(&self, row: usize, mut f: impl FnMut(usize, i64)) {
+        let weights = self.weights;
+        let (width, height) = (weights.width, weights.height);
+        let radius = if self.dense[row] {
+            weights.region.reach()
+        } else {
+            self.radius
+        };
+        let (x, y) = (row % width, row / width);
+        let target = weights.target[row];
+        let color_weight = weights.weights[row];
+        let spatial_weight = weights.settings.proximity_importance;
+        let cost = weights.settings.cost();
+        // same candidates as `ImgDiffWeights::window`, walked by coordinate to skip the div/mod
+        for cy in y.saturating_sub(radius)..=(y + radius).min(height - 1) {
+            for cx in x.saturating_sub(radius)..=(x + radius).min(width - 1) {
+                let col = cy * width + cx;
+                if !weights.region.allows(row, col) {
+                    continue;
+                }
+                f(
+                    col,
+                    cost.cost(
+                        (x as u32, y as u32),
+                        (cx as u32, cy as u32),
+                        target,
+                        weights.source[col],
+                        color_weight,
+                        spatial_weight,
+                    ) + weights
+                        .pins
+                        .cost((cx as u32, cy as u32), (x as u32, y as u32)),
+                );
+            }
+        }
+        for col in weights.pin_window(row, radius) {
+            f(col, self.cost(row, col));
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::app::calculate::color::squared;
+
+// sobel responses are divided by this to fit a byte, a hard black/white edge ends up near ±128
+const GRADIENT_SCALE: i32 = 8;
+/// Upper bound of `distance` between any two descriptors.
+pub const MAX_DISTANCE: i64 = 3 * 255 * 255;
+
+/// Describes the 3×3 neighbourhood of every cell on a row-major grid `width` cells wide as (horizontal gradient,
+/// vertical gradient, contrast). Gradients are sobel responses on luma offset by 128, contrast is
+/// twice the standard deviation of the neighbourhood's luma. Cells on the border repeat it.
+pub fn describe(pixels: &[(u8, u8, u8)], width: usize) -> Vec<(u8, u8, u8)> {
+    let luma = pixels
+        .iter()
+        .map(|&(r, g, b)| (r as i32 * 299 + g as i32 * 587 + b as i32 * 114) / 1000)
+        .collect::<Vec<_>>();
+    let last_x = width as isize - 1;
+    let last_y = (pixels.len() / width.max(1)) as isize - 1;
+
+    (0..pixels.len())
+        .map(|i| {
+            let (x, y) = ((i % width) as isize, (i / width) as isize);
+            let mut n = [[0; 3]; 3];
+            for (dy, row) in n.iter_mut().enumerate() {
+                for (dx, v) in row.iter_mut().enumerate() {
+                    let nx = (x + dx as isize - 1).clamp(0, last_x) as usize;
+                    let ny = (y + dy as isize - 1).clamp(0, last_y) as usize;
+                    *v = luma[ny * width + nx];
+                }
+            }
+
+            let gx = (n[0][2] + 2 * n[1][2] + n[2][2]) - (n[0][0] + 2 * n[1][0] + n[2][0]);
+            let gy = (n[2][0] + 2 * n[2][1] + n[2][2]) - (n[0][0] + 2 * n[0][1] + n[0][2]);
+
+            let sum = n.iter().flatten().sum::<i32>();
+            let sum_sq = n.iter().flatten().map(|v| v * v).sum::<i32>();
+            let variance = (sum_sq - sum * sum / 9) as f32 / 9.0;
+
+            (
+                (gx / GRADIENT_SCALE + 128).clamp(0, 255) as u8,
+                (gy / GRADIENT_SCALE + 128).clamp(0, 255) as u8,
+                (variance.max(0.0).sqrt() * 2.0).min(255.0) as u8,
+            )
+        })
+        .collect()
+}
+
+#[inline(always)]
+pub fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> i64 {
+    squared(a, b)
+}
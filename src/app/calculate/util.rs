@@ -1,11 +1,29 @@
+#[cfg(not(target_arch = "wasm32"))]
+use crate::app::calculate::JobMsg;
 use crate::app::calculate::ProgressMsg;
+use crate::app::calculate::annealing::Annealing;
+use crate::app::calculate::color::ColorSpace;
+use crate::app::calculate::cost::{Appearance, Cost, CostFunction, Features};
+use crate::app::calculate::error::{CalculateError, ImageKind};
+use crate::app::calculate::pins::Pin;
+use crate::app::calculate::texture;
+use crate::app::preset::UnprocessedPreset;
 
 use image::imageops;
 use serde::Deserialize;
 use serde::Serialize;
 use uuid::Uuid;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{JsCast, JsValue};
+#[cfg(target_arch = "wasm32")]
+use web_sys::js_sys;
 
-use std::error::Error;
+#[cfg(target_arch = "wasm32")]
+use std::cell::Cell;
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 // pub(crate) fn save_result(
 //     target: image::SourceImg,
@@ -34,10 +52,17 @@ use std::error::Error;
 pub trait ProgressSink {
     fn send(&mut self, msg: ProgressMsg);
 }
-// Native-friendly adapter
-impl ProgressSink for std::sync::mpsc::SyncSender<ProgressMsg> {
+/// Native-friendly adapter, tags everything a job sends with its id.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct JobSink {
+    pub id: Uuid,
+    pub tx: std::sync::mpsc::SyncSender<JobMsg>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ProgressSink for JobSink {
     fn send(&mut self, msg: ProgressMsg) {
-        let _ = std::sync::mpsc::SyncSender::send(self, msg);
+        let _ = self.tx.send(JobMsg { id: self.id, msg });
     }
 }
 
@@ -51,12 +76,236 @@ where
     }
 }
 
-#[allow(clippy::type_complexity)]
+/// Flags the GUI raises to stop a running job early.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+pub struct JobControl {
+    cancel: AtomicBool,
+    finish: AtomicBool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl JobControl {
+    /// Stops the job and throws its work away.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// Stops the job and has it hand in the best result it found so far.
+    pub fn finish(&self) {
+        self.finish.store(true, Ordering::Relaxed);
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    pub fn finishing(&self) -> bool {
+        self.finish.load(Ordering::Relaxed)
+    }
+}
+
+/// Flags the GUI raises to stop a running job early. Where the page is cross-origin isolated
+/// they live in a `SharedArrayBuffer` both sides see, so the worker notices them right away.
+/// Elsewhere `is_shared` is false and each side keeps its own copy: the GUI sends
+/// `WorkerReq::Cancel` or `WorkerReq::Finish` along, and the worker raises the flag when it
+/// reads that message at the job's next `Budget::pause`.
+#[cfg(target_arch = "wasm32")]
+pub struct JobControl {
+    flags: Option<js_sys::Int32Array>,
+    local: [Cell<bool>; 2],
+}
+
+#[cfg(target_arch = "wasm32")]
+const CANCEL_FLAG: u32 = 0;
+#[cfg(target_arch = "wasm32")]
+const FINISH_FLAG: u32 = 1;
+
+#[cfg(target_arch = "wasm32")]
+impl Default for JobControl {
+    fn default() -> Self {
+        let isolated = js_sys::Reflect::get(&js_sys::global(), &"crossOriginIsolated".into())
+            .is_ok_and(|v| v.is_truthy());
+        Self {
+            flags: isolated
+                .then(|| js_sys::Int32Array::new(&js_sys::SharedArrayBuffer::new(2 * 4).into())),
+            local: Default::default(),
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl JobControl {
+    /// The flags as seen from the worker, `buffer` is what `shared` handed to it.
+    pub fn from_shared(buffer: &JsValue) -> Self {
+        Self {
+            flags: buffer
+                .is_instance_of::<js_sys::SharedArrayBuffer>()
+                .then(|| js_sys::Int32Array::new(buffer)),
+            local: Default::default(),
+        }
+    }
+
+    /// The buffer to send along with the job, undefined if there is none.
+    pub fn shared(&self) -> JsValue {
+        self.flags
+            .as_ref()
+            .map_or(JsValue::UNDEFINED, |flags| flags.buffer().into())
+    }
+
+    /// Whether a running job sees `cancel` and `finish` without being sent a message.
+    pub fn is_shared(&self) -> bool {
+        self.flags.is_some()
+    }
+
+    /// Stops the job and throws its work away.
+    pub fn cancel(&self) {
+        self.set(CANCEL_FLAG);
+    }
+
+    /// Stops the job and has it hand in the best result it found so far.
+    pub fn finish(&self) {
+        self.set(FINISH_FLAG);
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.get(CANCEL_FLAG)
+    }
+
+    pub fn finishing(&self) -> bool {
+        self.get(FINISH_FLAG)
+    }
+
+    fn set(&self, flag: u32) {
+        match &self.flags {
+            Some(flags) => {
+                js_sys::Atomics::store(flags, flag, 1).ok();
+            }
+            None => self.local[flag as usize].set(true),
+        }
+    }
+
+    fn get(&self, flag: u32) -> bool {
+        match &self.flags {
+            Some(flags) => js_sys::Atomics::load(flags, flag) == Ok(1),
+            None => self.local[flag as usize].get(),
+        }
+    }
+}
+
+// how long a job on the worker runs between chances to read its messages
+#[cfg(target_arch = "wasm32")]
+const PAUSE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Decides when an iterative solver stops early and hands in its best result: once the job's
+/// `time_budget` or `max_iterations` runs out, or when "finish now" is pressed. Solvers `tick`
+/// once per iteration, which for most of them is once per progress update.
+pub(crate) struct Budget {
+    start: web_time::Instant,
+    time_budget: Option<Duration>,
+    max_iterations: Option<u32>,
+    iterations: u32,
+    control: Arc<JobControl>,
+    #[cfg(target_arch = "wasm32")]
+    last_pause: web_time::Instant,
+}
+
+impl Budget {
+    pub fn new(settings: &GenerationSettings, control: Arc<JobControl>) -> Self {
+        Self {
+            start: web_time::Instant::now(),
+            time_budget: settings
+                .time_budget
+                .map(|secs| Duration::from_secs_f32(secs.max(0.0))),
+            max_iterations: settings.max_iterations,
+            iterations: 0,
+            control,
+            #[cfg(target_arch = "wasm32")]
+            last_pause: web_time::Instant::now(),
+        }
+    }
+
+    /// Counts one iteration and returns whether the solver should stop now.
+    pub fn tick(&mut self) -> bool {
+        self.iterations += 1;
+        self.spent()
+    }
+
+    /// Whether the solver should stop, without counting an iteration.
+    pub fn spent(&self) -> bool {
+        self.control.finishing()
+            || self
+                .max_iterations
+                .is_some_and(|max| self.iterations >= max)
+            || self
+                .time_budget
+                .is_some_and(|budget| self.start.elapsed() >= budget)
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.control.cancelled()
+    }
+
+    /// Gives the worker a chance to read its messages, at most every `PAUSE_INTERVAL`, so a
+    /// `WorkerReq::Cancel` or `WorkerReq::Finish` sent mid-job reaches `cancelled` and `spent`.
+    /// Solvers await it between generations. It returns at once on native and where the flags
+    /// are shared, both of which see the GUI's flags without it.
+    pub async fn pause(&mut self) {
+        #[cfg(target_arch = "wasm32")]
+        if !self.control.is_shared() && self.last_pause.elapsed() >= PAUSE_INTERVAL {
+            // a timeout rather than a resolved promise, messages are only read between tasks
+            let timeout = js_sys::Promise::new(&mut |resolve, _| {
+                let global: web_sys::WorkerGlobalScope = js_sys::global().unchecked_into();
+                global.set_timeout_with_callback(&resolve).ok();
+            });
+            wasm_bindgen_futures::JsFuture::from(timeout).await.ok();
+            self.last_pause = web_time::Instant::now();
+        }
+    }
+}
+
+/// The cells of one job, cropped and resampled to the grid.
+pub(crate) struct Images {
+    /// Source cells in sRGB, for previews and the finished preset.
+    pub source_pixels: Vec<(u8, u8, u8)>,
+    /// What the cost compares, see `cost::Features`.
+    pub source: Vec<Features>,
+    pub target: Vec<Features>,
+    pub weights: Vec<i64>,
+}
+
+/// An RGB image from raw bytes, rejecting empty images and buffers that don't match the size.
+pub(crate) fn image_from_raw(
+    image: ImageKind,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+) -> Result<SourceImg, CalculateError> {
+    let invalid = CalculateError::InvalidDimensions {
+        image,
+        width,
+        height,
+    };
+    if width == 0 || height == 0 {
+        return Err(invalid);
+    }
+    image::ImageBuffer::from_vec(width, height, data).ok_or(invalid)
+}
+
+pub(crate) fn source_image(unprocessed: &UnprocessedPreset) -> Result<SourceImg, CalculateError> {
+    image_from_raw(
+        ImageKind::Source,
+        unprocessed.width,
+        unprocessed.height,
+        unprocessed.source_img.clone(),
+    )
+}
+
 pub(crate) fn get_images(
     source: SourceImg,
     settings: &GenerationSettings,
-) -> Result<(Vec<(u8, u8, u8)>, Vec<(u8, u8, u8)>, Vec<i64>), Box<dyn Error>> {
-    let source = settings.source_crop_scale.apply(&source, settings.sidelen);
+) -> Result<Images, CalculateError> {
+    let source = settings.source_crop_scale.apply(&source, settings.grid());
     let source_pixels = source
         .pixels()
         .map(|p| (p[0], p[1], p[2]))
@@ -67,8 +316,24 @@ pub(crate) fn get_images(
         .pixels()
         .map(|p| (p[0], p[1], p[2]))
         .collect::<Vec<_>>();
-    assert_eq!(source_pixels.len(), target_pixels.len());
-    Ok((source_pixels, target_pixels, weights))
+    for (image, found) in [
+        (ImageKind::Target, target_pixels.len()),
+        (ImageKind::Weights, weights.len()),
+    ] {
+        if found != source_pixels.len() {
+            return Err(CalculateError::MismatchedBuffers {
+                image,
+                expected: source_pixels.len(),
+                found,
+            });
+        }
+    }
+    Ok(Images {
+        source: settings.features(&source_pixels),
+        target: settings.features(&target_pixels),
+        source_pixels,
+        weights,
+    })
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize, PartialEq)]
@@ -87,32 +352,43 @@ impl CropScale {
         }
     }
 
-    pub fn apply(&self, img: &SourceImg, sidelen: u32) -> SourceImg {
+    /// Crops `img` to the shape of a `grid` of cells and resamples it to one pixel per cell.
+    pub fn apply(&self, img: &SourceImg, grid: (u32, u32)) -> SourceImg {
         let (w, h) = img.dimensions();
+        let (x0, y0, cw, ch) = self.crop_rect(w, h, grid);
+        let cropped = imageops::crop_imm(img, x0, y0, cw, ch).to_image();
 
-        let s = self.scale.max(1.0);
+        if (cw, ch) == grid {
+            cropped
+        } else {
+            imageops::resize(&cropped, grid.0, grid.1, imageops::FilterType::Lanczos3)
+        }
+    }
 
-        let base_side = w.min(h) as f32;
-        let mut crop_side = (base_side / s).floor().max(1.0);
+    /// The `(x, y, width, height)` of a `w`×`h` image that `apply` keeps for a grid shaped like
+    /// `aspect`.
+    pub fn crop_rect(&self, w: u32, h: u32, aspect: (u32, u32)) -> (u32, u32, u32, u32) {
+        let s = self.scale.max(1.0);
 
-        crop_side = crop_side.min(w as f32).min(h as f32);
+        // the largest rectangle of the grid's shape that fits the image
+        let ratio = aspect.0.max(1) as f32 / aspect.1.max(1) as f32;
+        let (base_w, base_h) = if (w as f32) > h as f32 * ratio {
+            (h as f32 * ratio, h as f32)
+        } else {
+            (w as f32, w as f32 / ratio)
+        };
+        let crop_w = (base_w / s).floor().max(1.0).min(w as f32);
+        let crop_h = (base_h / s).floor().max(1.0).min(h as f32);
 
-        let max_x_off = (w as f32 - crop_side).max(0.0);
-        let max_y_off = (h as f32 - crop_side).max(0.0);
+        let max_x_off = (w as f32 - crop_w).max(0.0);
+        let max_y_off = (h as f32 - crop_h).max(0.0);
 
         let xn = (self.x.clamp(-1.0, 1.0) + 1.0) * 0.5;
         let yn = (self.y.clamp(-1.0, 1.0) + 1.0) * 0.5;
 
         let x0 = (xn * max_x_off).floor() as u32;
         let y0 = (yn * max_y_off).floor() as u32;
-        let cs = crop_side as u32;
-        let cropped = imageops::crop_imm(img, x0, y0, cs, cs).to_image();
-
-        if cs == sidelen {
-            cropped
-        } else {
-            imageops::resize(&cropped, sidelen, sidelen, imageops::FilterType::Lanczos3)
-        }
+        (x0, y0, crop_w as u32, crop_h as u32)
     }
 }
 
@@ -120,18 +396,62 @@ impl CropScale {
 pub enum Algorithm {
     Optimal,
     Genetic,
+    Auction,
+    Multiscale,
+    Sinkhorn,
 }
 
+// longest side of a weight map or mask started by painting, plenty for the largest grid's cells
+const PAINT_SIDE: u32 = 512;
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct GenerationSettings {
     pub id: Uuid,
     pub name: String,
 
     pub proximity_importance: i64,
+    pub cost_function: CostFunction,
+    pub color_space: ColorSpace,
+    /// How much local structure (edges, texture) counts next to colour, 0 ignores it.
+    pub texture_importance: i64,
+    /// How strongly the swap solvers keep neighbouring cells moving the same way, 0 disables it.
+    pub motion_coherence: i64,
+    /// Furthest any cell may travel, in cells, enforced by every solver. `None` for no limit.
+    pub max_displacement: Option<u32>,
+    /// Seed for the random swaps of the swap-based solvers.
+    pub seed: u64,
+    /// Anneal instead of only taking improving swaps, for the genetic solver.
+    pub annealing: Option<Annealing>,
+    /// Run the swap-based solvers on every core, native only. Off by default: swaps stay inside
+    /// tiles, so the long early swaps of the serial solver are lost and results are a bit worse.
+    pub parallel: bool,
+    /// Assignment to start from instead of the identity, e.g. the loaded preset's. Only used by
+    /// the genetic and auction solvers, see `initial_assignment`.
+    pub warm_start: Option<Vec<usize>>,
+    /// Seconds after which the iterative solvers stop and hand in their best result so far.
+    pub time_budget: Option<f32>,
+    /// Iterations after which the iterative solvers stop, see `Budget`.
+    pub max_iterations: Option<u32>,
     pub algorithm: Algorithm,
+    /// Blur of the entropic transport plan, relative to the largest cost spread in a window.
+    pub sinkhorn_epsilon: f32,
 
+    /// Cells along the grid's longer side, the other side follows `aspect`.
     pub sidelen: u32,
+    /// Width to height of the grid, e.g. `(16, 9)`. Source and target are cropped to it.
+    pub aspect: (u32, u32),
     custom_target: Option<(u32, u32, Vec<u8>)>,
+    /// Greyscale importance of each part of the target, white counts most. Stretched over the
+    /// whole target and cropped with it. Without one the default target uses `weights256.png`
+    /// and custom targets uniform weights.
+    custom_weights: Option<(u32, u32, Vec<u8>)>,
+    /// Scale the target's weights by `edge_weights`, so edges and detail are matched more closely.
+    pub edge_boost: bool,
+    /// Source points that should land on target points, see `pins::Pins`.
+    pub pins: Vec<Pin>,
+    /// Which part of the grid morphs, white inside, stretched over the cropped images. Cells
+    /// outside stay where they are, see `region::Region`. Without one everything morphs.
+    mask: Option<(u32, u32, Vec<u8>)>,
     pub target_crop_scale: CropScale,
     pub source_crop_scale: CropScale,
 }
@@ -143,37 +463,141 @@ impl GenerationSettings {
         Self {
             name,
             proximity_importance: 13, // 20
+            cost_function: CostFunction::Quartic,
+            color_space: ColorSpace::Rgb,
+            texture_importance: 0,
+            motion_coherence: 0,
+            max_displacement: None,
+            seed: 12345,
+            annealing: None,
+            parallel: false,
+            warm_start: None,
+            time_budget: None,
+            max_iterations: None,
             algorithm: Algorithm::Genetic,
+            sinkhorn_epsilon: 0.01,
             id,
             sidelen: 128,
+            aspect: (1, 1),
             custom_target: None,
+            custom_weights: None,
+            pins: vec![],
+            mask: None,
+            edge_boost: false,
             target_crop_scale: CropScale::identity(),
             source_crop_scale: CropScale::identity(),
         }
     }
 
-    pub fn get_target(&self) -> Result<(SourceImg, Vec<i64>), Box<dyn std::error::Error>> {
-        let target = self.get_raw_target();
-        let target = self.target_crop_scale.apply(&target, self.sidelen);
-        let weights = if self.custom_target.is_some() {
-            vec![255; (self.sidelen * self.sidelen) as usize] // uniform weights
+    /// Cells across and down the grid.
+    pub fn grid(&self) -> (u32, u32) {
+        self.grid_at(self.sidelen)
+    }
+
+    /// Cells across and down a grid of `aspect` with `longest` cells along its longer side.
+    pub fn grid_at(&self, longest: u32) -> (u32, u32) {
+        let (aw, ah) = (self.aspect.0.max(1) as u64, self.aspect.1.max(1) as u64);
+        let shorter = |long: u64, short: u64| {
+            ((longest as u64 * short + long / 2) / long).clamp(1, longest.max(1) as u64) as u32
+        };
+        if aw >= ah {
+            (longest, shorter(aw, ah))
         } else {
-            let target_weights =
-                image::load_from_memory(include_bytes!("weights256.png"))?.to_rgb8();
-            let target_weights = self.target_crop_scale.apply(&target_weights, self.sidelen);
-            load_weights(target_weights)
+            (shorter(ah, aw), longest)
+        }
+    }
+
+    pub fn cost(&self) -> Cost {
+        Cost {
+            function: self.cost_function,
+            appearance: Appearance {
+                color_space: self.color_space,
+                texture_importance: self.texture_importance,
+            },
+        }
+    }
+
+    /// `warm_start`, if it maps every cell of this grid to a different source cell. A start from
+    /// a different resolution or a corrupt preset is ignored rather than failing the job.
+    pub(crate) fn initial_assignment(&self) -> Option<&[usize]> {
+        let assignments = self.warm_start.as_deref()?;
+        let (w, h) = self.grid();
+        let cells = (w * h) as usize;
+        if assignments.len() != cells {
+            return None;
+        }
+        let mut used = vec![false; cells];
+        for &src in assignments {
+            if src >= cells || std::mem::replace(&mut used[src], true) {
+                return None;
+            }
+        }
+        Some(assignments)
+    }
+
+    /// Features of a grid of sRGB cells for the cost.
+    pub(crate) fn features(&self, pixels: &[(u8, u8, u8)]) -> Vec<Features> {
+        let textures = texture::describe(pixels, self.grid().0 as usize);
+        pixels
+            .iter()
+            .zip(textures)
+            .map(|(&rgb, texture)| Features {
+                color: self.color_space.encode(rgb),
+                texture,
+            })
+            .collect()
+    }
+
+    pub fn get_target(&self) -> Result<(SourceImg, Vec<i64>), CalculateError> {
+        self.target_at(self.grid())
+    }
+
+    /// The cropped target and its weights on a `grid` of cells across and down.
+    pub(crate) fn target_at(
+        &self,
+        grid: (u32, u32),
+    ) -> Result<(SourceImg, Vec<i64>), CalculateError> {
+        let raw_target = self.get_raw_target()?;
+        let target = self.target_crop_scale.apply(&raw_target, grid);
+        let weights = match self.weight_map(raw_target.dimensions())? {
+            Some(target_weights) => {
+                load_weights(self.target_crop_scale.apply(&target_weights, grid))
+            }
+            None => vec![255; (grid.0 * grid.1) as usize], // uniform weights
+        };
+        let weights = if self.edge_boost {
+            weights
+                .iter()
+                .zip(edge_weights(&target))
+                .map(|(w, boost)| w * boost / 255)
+                .collect()
+        } else {
+            weights
         };
 
         Ok((target, weights))
     }
 
-    pub(crate) fn get_raw_target(&self) -> SourceImg {
+    /// The weight map in use stretched to `w`×`h`, `None` for uniform weights.
+    fn weight_map(&self, (w, h): (u32, u32)) -> Result<Option<SourceImg>, CalculateError> {
+        let target_weights = match self.get_raw_weights()? {
+            Some(target_weights) => target_weights,
+            None if self.custom_target.is_some() => return Ok(None),
+            None => image::load_from_memory(include_bytes!("weights256.png"))?.to_rgb8(),
+        };
+        // stretched over the target in case the map was made at another size
+        Ok(Some(if target_weights.dimensions() == (w, h) {
+            target_weights
+        } else {
+            imageops::resize(&target_weights, w, h, imageops::FilterType::Triangle)
+        }))
+    }
+
+    pub(crate) fn get_raw_target(&self) -> Result<SourceImg, CalculateError> {
         if let Some((w, h, data)) = &self.custom_target {
-            image::ImageBuffer::from_vec(*w, *h, data.clone()).unwrap()
+            image_from_raw(ImageKind::Target, *w, *h, data.clone())
         } else {
-            image::load_from_memory(include_bytes!("target256.png"))
-                .unwrap()
-                .to_rgb8()
+            Ok(image::load_from_memory(include_bytes!("target256.png"))?.to_rgb8())
         }
     }
 
@@ -181,6 +605,157 @@ impl GenerationSettings {
         let (w, h) = img.dimensions();
         let data = img.into_raw();
         self.custom_target = Some((w, h, data));
+        // a map drawn for another target would emphasise the wrong places
+        self.custom_weights = None;
+    }
+
+    pub(crate) fn has_custom_target(&self) -> bool {
+        self.custom_target.is_some()
+    }
+
+    pub(crate) fn has_custom_weights(&self) -> bool {
+        self.custom_weights.is_some()
+    }
+
+    /// The custom target's weight map as RGB, greyscale in every channel like `weights256.png`.
+    pub(crate) fn get_raw_weights(&self) -> Result<Option<SourceImg>, CalculateError> {
+        let Some((w, h, data)) = &self.custom_weights else {
+            return Ok(None);
+        };
+        let invalid = CalculateError::InvalidDimensions {
+            image: ImageKind::Weights,
+            width: *w,
+            height: *h,
+        };
+        if *w == 0 || *h == 0 {
+            return Err(invalid);
+        }
+        let grey = image::GrayImage::from_vec(*w, *h, data.clone()).ok_or(invalid)?;
+        Ok(Some(image::DynamicImage::ImageLuma8(grey).to_rgb8()))
+    }
+
+    /// Attaches a weight map to the custom target, ignored without one.
+    pub(crate) fn set_raw_weights(&mut self, img: image::GrayImage) {
+        let (w, h) = img.dimensions();
+        self.custom_weights = Some((w, h, img.into_raw()));
+    }
+
+    pub(crate) fn clear_weights(&mut self) {
+        self.custom_weights = None;
+    }
+
+    /// One dab of a soft round brush on the weight map, which starts out as a copy of the map in
+    /// use. `center` is in fractions of the target's width and height, `radius` in fractions of
+    /// its shorter side. `strength` (0..=1) is how far the middle of the dab moves towards white,
+    /// or towards black when `erase`.
+    pub(crate) fn paint_weights(
+        &mut self,
+        center: (f32, f32),
+        radius: f32,
+        strength: f32,
+        erase: bool,
+    ) -> Result<(), CalculateError> {
+        if self.custom_weights.is_none() {
+            let (w, h) = self.get_raw_target()?.dimensions();
+            let scale = (PAINT_SIDE as f32 / w.max(h) as f32).min(1.0);
+            let w = ((w as f32 * scale).round() as u32).max(1);
+            let h = ((h as f32 * scale).round() as u32).max(1);
+            let map = match self.weight_map((w, h))? {
+                Some(map) => image::DynamicImage::ImageRgb8(map).to_luma8(),
+                None => image::GrayImage::from_pixel(w, h, image::Luma([255])),
+            };
+            self.set_raw_weights(map);
+        }
+        let Some((w, h, data)) = &mut self.custom_weights else {
+            return Ok(());
+        };
+        let (w, h) = (*w as usize, *h as usize);
+        if w == 0 || h == 0 || data.len() != w * h {
+            return Err(CalculateError::InvalidDimensions {
+                image: ImageKind::Weights,
+                width: w as u32,
+                height: h as u32,
+            });
+        }
+
+        dab(w, h, center, radius, |i, falloff| {
+            let amount = strength.clamp(0.0, 1.0) * falloff * 255.0;
+            let v = &mut data[i];
+            let new = if erase {
+                *v as f32 - amount
+            } else {
+                *v as f32 + amount
+            };
+            *v = new.round().clamp(0.0, 255.0) as u8;
+        });
+        Ok(())
+    }
+
+    /// Whether each cell of a `grid` of cells across and down is inside the mask, `None` without
+    /// one.
+    pub(crate) fn mask_at(
+        &self,
+        (gw, gh): (u32, u32),
+    ) -> Result<Option<Vec<bool>>, CalculateError> {
+        let Some((w, h, data)) = &self.mask else {
+            return Ok(None);
+        };
+        let (w, h) = (*w as usize, *h as usize);
+        if w == 0 || h == 0 || data.len() != w * h {
+            return Err(CalculateError::InvalidDimensions {
+                image: ImageKind::Mask,
+                width: w as u32,
+                height: h as u32,
+            });
+        }
+        let (gw, gh) = (gw as usize, gh as usize);
+        Ok(Some(
+            (0..gw * gh)
+                .map(|cell| {
+                    // the mask pixel under the cell's centre
+                    let x = ((cell % gw * 2 + 1) * w / (gw * 2)).min(w - 1);
+                    let y = ((cell / gw * 2 + 1) * h / (gh * 2)).min(h - 1);
+                    data[y * w + x] >= 128
+                })
+                .collect(),
+        ))
+    }
+
+    pub(crate) fn has_mask(&self) -> bool {
+        self.mask.is_some()
+    }
+
+    pub(crate) fn clear_mask(&mut self) {
+        self.mask = None;
+    }
+
+    /// One dab of a hard round brush on the mask, which starts out empty and shaped like the grid
+    /// so nothing morphs until some of it is painted. `center` is in fractions of the grid's width
+    /// and height, `radius` in fractions of its shorter side. `erase` takes cells out of the
+    /// region again.
+    pub(crate) fn paint_mask(
+        &mut self,
+        center: (f32, f32),
+        radius: f32,
+        erase: bool,
+    ) -> Result<(), CalculateError> {
+        let (mw, mh) = self.grid_at(PAINT_SIDE);
+        let (w, h, data) = self
+            .mask
+            .get_or_insert_with(|| (mw, mh, vec![0; (mw * mh) as usize]));
+        let (w, h) = (*w as usize, *h as usize);
+        if w == 0 || h == 0 || data.len() != w * h {
+            return Err(CalculateError::InvalidDimensions {
+                image: ImageKind::Mask,
+                width: w as u32,
+                height: h as u32,
+            });
+        }
+
+        dab(w, h, center, radius, |i, _| {
+            data[i] = if erase { 0 } else { 255 };
+        });
+        Ok(())
     }
 
     #[allow(dead_code)]
@@ -204,6 +779,32 @@ impl GenerationSettings {
     }
 }
 
+/// Calls `f` with the index of every pixel of a `width`×`height` image whose centre is inside a
+/// round brush, and how close to the middle it is (1 there, 0 at the rim). `center` is in
+/// fractions of the image's width and height, `radius` in fractions of its shorter side.
+fn dab(
+    width: usize,
+    height: usize,
+    center: (f32, f32),
+    radius: f32,
+    mut f: impl FnMut(usize, f32),
+) {
+    let (cx, cy) = (center.0 * width as f32, center.1 * height as f32);
+    let r = (radius * width.min(height) as f32).max(0.5);
+    let x0 = (cx - r).floor().max(0.0) as usize;
+    let y0 = (cy - r).floor().max(0.0) as usize;
+    let x1 = ((cx + r).ceil().max(0.0) as usize).min(width);
+    let y1 = ((cy + r).ceil().max(0.0) as usize).min(height);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let d = ((x as f32 + 0.5 - cx).powi(2) + (y as f32 + 0.5 - cy).powi(2)).sqrt();
+            if d < r {
+                f(y * width + x, 1.0 - d / r);
+            }
+        }
+    }
+}
+
 pub fn load_weights(source: SourceImg) -> Vec<i64> {
     let (width, height) = source.dimensions();
     let mut weights = vec![0; (width * height) as usize];
@@ -213,3 +814,90 @@ pub fn load_weights(source: SourceImg) -> Vec<i64> {
     }
     weights
 }
+
+// how much each cue counts in `edge_weights`, they add up to 1
+const EDGE_GRADIENT: f32 = 0.45;
+const EDGE_CONTRAST: f32 = 0.35;
+const EDGE_CENTRE: f32 = 0.2;
+// share of the weight every cell keeps, so flat areas still care about colour
+const EDGE_FLOOR: f32 = 0.25;
+// local contrast is the standard deviation over a window this many cells out from the centre
+const CONTRAST_RADIUS: i64 = 2;
+
+/// Per-cell weights for "edge boost": gradient magnitude, local contrast and a bias towards the
+/// middle of the (already cropped) target, averaging 255 so colour keeps the same overall
+/// importance next to proximity.
+pub(crate) fn edge_weights(target: &SourceImg) -> Vec<i64> {
+    let (w, h) = (target.width() as i64, target.height() as i64);
+    let luma = target
+        .pixels()
+        .map(|p| 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32)
+        .collect::<Vec<_>>();
+    let at = |x: i64, y: i64| luma[(y.clamp(0, h - 1) * w + x.clamp(0, w - 1)) as usize];
+
+    let mut gradient = Vec::with_capacity(luma.len());
+    let mut contrast = Vec::with_capacity(luma.len());
+    let mut centre = Vec::with_capacity(luma.len());
+    for y in 0..h {
+        for x in 0..w {
+            // sobel
+            let gx = at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2.0 * at(x - 1, y)
+                - at(x - 1, y + 1);
+            let gy = at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2.0 * at(x, y - 1)
+                - at(x + 1, y - 1);
+            gradient.push((gx * gx + gy * gy).sqrt());
+
+            let (mut sum, mut sum_sq, mut n) = (0.0, 0.0, 0.0);
+            for dy in -CONTRAST_RADIUS..=CONTRAST_RADIUS {
+                for dx in -CONTRAST_RADIUS..=CONTRAST_RADIUS {
+                    let v = at(x + dx, y + dy);
+                    sum += v;
+                    sum_sq += v * v;
+                    n += 1.0;
+                }
+            }
+            let mean = sum / n;
+            contrast.push((sum_sq / n - mean * mean).max(0.0).sqrt());
+
+            // 1 in the middle, close to 0 in the corners
+            let dx = (x as f32 + 0.5) / w as f32 - 0.5;
+            let dy = (y as f32 + 0.5) / h as f32 - 0.5;
+            centre.push((-(dx * dx + dy * dy) / 0.125).exp());
+        }
+    }
+    normalize(&mut gradient);
+    normalize(&mut contrast);
+
+    let boost = gradient
+        .iter()
+        .zip(&contrast)
+        .zip(&centre)
+        .map(|((g, c), m)| {
+            EDGE_FLOOR
+                + (1.0 - EDGE_FLOOR) * (EDGE_GRADIENT * g + EDGE_CONTRAST * c + EDGE_CENTRE * m)
+        })
+        .collect::<Vec<_>>();
+    let mean = boost.iter().sum::<f32>() / boost.len().max(1) as f32;
+    boost
+        .iter()
+        .map(|b| (255.0 * b / mean).round() as i64)
+        .collect()
+}
+
+/// Scales `values` to 0..=1, with the top few percent all at 1 so a handful of sharp edges
+/// don't flatten everything else.
+fn normalize(values: &mut [f32]) {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let Some(&top) = sorted.get(sorted.len().saturating_sub(1) * 95 / 100) else {
+        return;
+    };
+    let top = top.max(f32::EPSILON);
+    for v in values {
+        *v = (*v / top).min(1.0);
+    }
+}
@@ -3,54 +3,88 @@ use serde::{Deserialize, Serialize};
 use web_sys::DedicatedWorkerGlobalScope;
 use web_sys::js_sys;
 
+// serialized as soon as it's made, the size never matters
+#[allow(clippy::large_enum_variant)]
 #[derive(Serialize, Deserialize)]
 pub enum WorkerReq {
     Process {
         source: crate::app::preset::UnprocessedPreset,
         settings: super::GenerationSettings,
+        /// `JobControl::shared` of the GUI's side of the job.
+        #[serde(with = "serde_wasm_bindgen::preserve")]
+        control: JsValue,
     },
+    /// Raises `JobControl::cancel` of the job with this id. A running job reads it at its next
+    /// `Budget::pause`, so it is only needed where the flags aren't shared.
+    Cancel { id: Uuid },
+    /// Raises `JobControl::finish` of the job with this id, like `Cancel`.
+    Finish { id: Uuid },
 }
 
-use crate::app::calculate::ProgressMsg;
-use crate::app::calculate::process;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use uuid::Uuid;
 
-// thread_local! {
-//     static CANCELLED: Rc<Cell<bool>> = Rc::new(Cell::new(false));
-// }
+use crate::app::calculate::process;
+use crate::app::calculate::util::JobControl;
+use crate::app::calculate::{JobMsg, ProgressMsg};
 
 #[wasm_bindgen]
 pub fn worker_entry() {
     let global: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
     let global_for_handler = global.clone();
+    // flags of the jobs started and not yet finished, for `Cancel` and `Finish`
+    let running: Rc<RefCell<HashMap<Uuid, Arc<JobControl>>>> = Rc::default();
 
     let handler = Closure::wrap(Box::new(move |e: web_sys::MessageEvent| {
         // Deserialize the incoming request
         let req: WorkerReq = match serde_wasm_bindgen::from_value(e.data()) {
             Ok(v) => v,
             Err(err) => {
-                let _ = global_for_handler.post_message(
-                    &serde_wasm_bindgen::to_value(&ProgressMsg::Error(format!("bad req: {err}")))
-                        .unwrap(),
-                );
+                // no job id to tag a reply with, so nothing in the app would pick it up
+                web_sys::console::error_1(&format!("bad req: {err}").into());
                 return;
             }
         };
 
         match req {
-            WorkerReq::Process { source, settings } => {
-                // Run job; if you need to keep the UI responsive in the worker,
-                // wrap in an async task and yield occasionally.
-                let global2 = global_for_handler.clone();
-
-                // progress sink -> postMessage
-                let mut sink = |msg: ProgressMsg| {
-                    let _ = global2.post_message(&serde_wasm_bindgen::to_value(&msg).unwrap());
-                };
+            WorkerReq::Process {
+                source,
+                settings,
+                control,
+            } => {
+                // single-threaded, the Arc only keeps `process` the same as on native
+                #[allow(clippy::arc_with_non_send_sync)]
+                let control = Arc::new(JobControl::from_shared(&control));
+                let id = settings.id;
+                running.borrow_mut().insert(id, control.clone());
 
-                // If you need to yield, you can insert tiny awaits between steps.
-                // Here we just call the portable sync fn:
-                if let Err(e) = process(source, settings, &mut sink) {
-                    sink(ProgressMsg::Error(e.to_string()));
+                // the job pauses now and then to let this handler read `Cancel` and `Finish`
+                let global2 = global_for_handler.clone();
+                let running = running.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    // progress sink -> postMessage, tagged with the job id
+                    let mut sink = |msg: ProgressMsg| {
+                        let msg = JobMsg { id, msg };
+                        let _ = global2.post_message(&serde_wasm_bindgen::to_value(&msg).unwrap());
+                    };
+                    if let Err(e) = process(source, settings, &mut sink, control).await {
+                        sink(ProgressMsg::Error(e));
+                    }
+                    running.borrow_mut().remove(&id);
+                });
+            }
+            WorkerReq::Cancel { id } => {
+                if let Some(control) = running.borrow().get(&id) {
+                    control.cancel();
+                }
+            }
+            WorkerReq::Finish { id } => {
+                if let Some(control) = running.borrow().get(&id) {
+                    control.finish();
                 }
             }
         }
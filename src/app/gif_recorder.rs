@@ -7,6 +7,7 @@ use color_quant::NeuQuant;
 use crate::{VantaMorphApp, app::SeedColor};
 
 pub const GIF_FRAMERATE: u32 = 8;
+/// Longer side of a recorded gif, the other follows the preset's grid.
 pub const GIF_RESOLUTION: u32 = 400;
 pub const GIF_MAX_FRAMES: u32 = 140;
 pub const GIF_MIN_FRAMES: u32 = 100;
@@ -45,6 +46,8 @@ pub struct GifRecorder {
     pub encoder: Option<gif::Encoder<Vec<u8>>>,
     pub palette: Option<NeuQuant>,
     pub frame_count: u32,
+    // width and height of the frames being recorded
+    size: (u32, u32),
     inflight: Option<InFlight>,
     should_stop: bool,
 }
@@ -57,6 +60,7 @@ impl GifRecorder {
             encoder: None,
             palette: None,
             frame_count: 0,
+            size: (GIF_RESOLUTION, GIF_RESOLUTION),
             inflight: None,
             should_stop: false,
         }
@@ -76,8 +80,7 @@ impl GifRecorder {
                 let slice = inflight.buffer.slice(..);
                 let mapped = slice.get_mapped_range();
                 // Remove row padding
-                let width = GIF_RESOLUTION;
-                let height = GIF_RESOLUTION;
+                let (width, height) = self.size;
                 let bpp = 4u32; // RGBA8
                 let unpadded_bytes_per_row = width * bpp;
                 let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT; // 256
@@ -110,8 +113,8 @@ impl GifRecorder {
                     .map(|pix| nq.index_of(pix) as u8)
                     .collect();
                 let mut frame = gif::Frame::from_indexed_pixels(
-                    GIF_RESOLUTION as u16,
-                    GIF_RESOLUTION as u16,
+                    self.size.0 as u16,
+                    self.size.1 as u16,
                     pixels,
                     None,
                 );
@@ -134,9 +137,11 @@ impl GifRecorder {
         }
     }
 
+    /// Starts a gif of `size` pixels, the size of the canvas while recording.
     pub fn init_encoder(
         &mut self,
         active_colors: &[SeedColor],
+        size: (u32, u32),
     ) -> Result<(), Box<dyn std::error::Error>> {
         let colors = active_colors
             .iter()
@@ -148,10 +153,11 @@ impl GifRecorder {
         let gif_palette = NeuQuant::new(GIF_PALETTE_SAMPLEFAC, 256, &colors);
         let mut encoder = gif::Encoder::new(
             vec![],
-            GIF_RESOLUTION as u16,
-            GIF_RESOLUTION as u16,
+            size.0 as u16,
+            size.1 as u16,
             &gif_palette.color_map_rgb(),
         )?;
+        self.size = size;
         self.palette = Some(gif_palette);
         encoder.set_repeat(gif::Repeat::Infinite)?;
         self.encoder = Some(encoder);
@@ -5,13 +5,19 @@ use super::GuiMode;
 use super::VantaMorphApp;
 use crate::app::DEFAULT_RESOLUTION;
 use crate::app::calculate;
-use crate::app::calculate::ProgressMsg;
+use crate::app::calculate::annealing::{Annealing, Cooling};
+use crate::app::calculate::color::ColorSpace;
+use crate::app::calculate::cost::CostFunction;
+use crate::app::calculate::pins::Pin;
 use crate::app::calculate::util::CropScale;
 use crate::app::calculate::util::GenerationSettings;
 use crate::app::calculate::util::SourceImg;
+use crate::app::calculate::{JobMsg, ProgressMsg};
 use crate::app::gif_recorder::GIF_FRAMERATE;
 use crate::app::gif_recorder::GIF_RESOLUTION;
 use crate::app::gif_recorder::GifStatus;
+use crate::app::jobs::{JobQueue, JobState};
+use crate::app::overlay::{AnalysisOverlay, AnalysisView};
 use crate::app::preset::Preset;
 use crate::app::preset::UnprocessedPreset;
 use eframe::App;
@@ -22,9 +28,6 @@ use egui::TextureHandle;
 use egui::Window;
 use image::buffer::ConvertBuffer;
 use image::imageops;
-use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering;
 use uuid::Uuid;
 
 // #[cfg(not(target_arch = "wasm32"))]
@@ -35,6 +38,32 @@ struct GuiImageCache {
     source_preview: Option<egui::TextureHandle>,
     target_preview: Option<egui::TextureHandle>,
     overlap_preview: Option<egui::TextureHandle>,
+    weight_preview: Option<egui::TextureHandle>,
+    mask_preview: Option<egui::TextureHandle>,
+}
+
+// grid shapes offered in the morph settings, width to height
+const ASPECTS: [(u32, u32); 7] = [(1, 1), (4, 3), (3, 2), (16, 9), (3, 4), (2, 3), (9, 16)];
+
+/// Brush for painting weights or the region mask over the target preview.
+pub struct WeightBrush {
+    /// Radius as a fraction of the preview's shorter side.
+    pub size: f32,
+    /// How fast a held brush moves the weights, 1 takes them from black to white in a
+    /// quarter second. The mask brush is always at full strength.
+    pub strength: f32,
+    /// Lower the weights, or take cells out of the region, instead.
+    pub erase: bool,
+}
+
+impl Default for WeightBrush {
+    fn default() -> Self {
+        Self {
+            size: 0.08,
+            strength: 0.5,
+            erase: false,
+        }
+    }
 }
 
 /// UI mode: Simple for beginners, Pro for advanced users
@@ -55,20 +84,15 @@ pub enum RightPanelTab {
 }
 
 /// Playback speed options
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Default)]
 pub enum PlaybackSpeed {
     Quarter, // 0.25x
     Half,    // 0.5x
-    Normal,  // 1x
+    #[default]
+    Normal, // 1x
     Double,  // 2x
 }
 
-impl Default for PlaybackSpeed {
-    fn default() -> Self {
-        PlaybackSpeed::Normal
-    }
-}
-
 impl PlaybackSpeed {
     fn multiplier(&self) -> f32 {
         match self {
@@ -130,9 +154,8 @@ pub(crate) struct GuiState {
     mode: GuiMode,
     pub animate: bool,
     //pub fps_text: String,
-    show_progress_modal: Option<Uuid>,
-    last_progress: f32,
-    process_cancelled: Arc<AtomicBool>,
+    /// Morphs waiting or running, shown in the job list while not empty.
+    pub jobs: JobQueue,
     //pub currently_processing: Option<Preset>,
     pub presets: Vec<Preset>,
     //pub current_settings: GenerationSettings,
@@ -147,9 +170,6 @@ pub(crate) struct GuiState {
     /// Pending image to auto-morph (set when user drops/uploads an image for automatic processing)
     pub pending_auto_morph: Option<(String, SourceImg)>,
 
-    /// When processing a preset click, this holds the index to replace instead of creating new
-    pub replacing_preset_index: Option<usize>,
-
     /// Pending preset to process on next frame (for initial load)
     pub pending_preset_process: Option<usize>,
 
@@ -227,6 +247,18 @@ pub(crate) struct GuiState {
     /// Show canvas overlays (particle count, fps, resolution)
     pub show_overlays: bool,
 
+    /// Cost/displacement heatmap or flow arrows drawn over the canvas
+    pub analysis: AnalysisOverlay,
+
+    /// Brush of the weight painter in the morph settings
+    pub weight_brush: WeightBrush,
+
+    /// Brush of the region mask painter in the morph settings
+    pub mask_brush: WeightBrush,
+
+    /// Source end of a pin waiting for its target end to be clicked
+    pub pending_pin: Option<(f32, f32)>,
+
     /// Project name
     pub project_name: String,
 }
@@ -252,9 +284,7 @@ impl GuiState {
             //fps_text: String::new(),
             presets,
             mode: GuiMode::Transform,
-            show_progress_modal: None,
-            last_progress: 0.0,
-            process_cancelled: Arc::new(AtomicBool::new(false)),
+            jobs: JobQueue::default(),
             #[cfg(not(target_arch = "wasm32"))]
             last_mouse_pos: None,
             #[cfg(not(target_arch = "wasm32"))]
@@ -268,7 +298,6 @@ impl GuiState {
             error_message: None,
             has_morphed_once,
             pending_auto_morph: None,
-            replacing_preset_index: None,
             pending_preset_process: Some(current_preset),
             frames_since_start: 0,
             // New UI state
@@ -300,20 +329,31 @@ impl GuiState {
             compare_view: CompareView::None,
             split_position: 0.5,
             show_overlays: true,
+            analysis: AnalysisOverlay::default(),
+            weight_brush: WeightBrush::default(),
+            mask_brush: WeightBrush::default(),
+            pending_pin: None,
             project_name: String::from("Untitled Project"),
         }
     }
 
-    fn show_progress_modal(&mut self, id: Uuid) {
-        self.show_progress_modal = Some(id);
+    fn queue_job(
+        &mut self,
+        source: UnprocessedPreset,
+        settings: GenerationSettings,
+        replacing: Option<Uuid>,
+    ) {
+        self.jobs.push(source, settings, replacing);
         #[cfg(target_arch = "wasm32")]
         hide_icons();
     }
 
-    fn hide_progress_modal(&mut self) {
-        self.show_progress_modal = None;
+    pub fn remove_job(&mut self, id: Uuid) {
+        self.jobs.remove(id);
         #[cfg(target_arch = "wasm32")]
-        show_icons();
+        if self.jobs.is_empty() {
+            show_icons();
+        }
     }
 
     fn show_error(&mut self, msg: String) {
@@ -434,10 +474,7 @@ impl App for VantaMorphApp {
         // Check for dropped/pasted images (WASM only)
         #[cfg(target_arch = "wasm32")]
         {
-            if self.gui.pending_auto_morph.is_none()
-                && self.gui.show_progress_modal.is_none()
-                && self.gui.configuring_generation.is_none()
-            {
+            if self.gui.pending_auto_morph.is_none() && self.gui.configuring_generation.is_none() {
                 if let Some((name, img)) = check_dropped_image() {
                     self.gui.pending_auto_morph = Some((name, img));
                 }
@@ -495,7 +532,7 @@ impl App for VantaMorphApp {
                         }
                         Ok(true) => {
                             for _ in 0..(60 / GIF_FRAMERATE) {
-                                self.sim.update(&mut self.seeds, self.size.0);
+                                self.sim.update(&mut self.seeds, self.size);
                             }
 
                             self.gif_recorder.frame_count += 1;
@@ -528,7 +565,7 @@ impl App for VantaMorphApp {
                     let updates = ((base_updates as f32) * speed_mult).max(1.0) as usize;
 
                     for _ in 0..updates {
-                        self.sim.update(&mut self.seeds, self.size.0);
+                        self.sim.update(&mut self.seeds, self.size);
                     }
                 }
                 rs.queue
@@ -591,23 +628,20 @@ impl App for VantaMorphApp {
         if let Some((name, img)) = self.gui.pending_auto_morph.take() {
             let img = ensure_reasonable_size(img);
             let mut settings = GenerationSettings::default(Uuid::new_v4(), name.clone());
+            settings.sidelen = self.gui.resolution;
+            settings.edge_boost = self.gui.edge_boost;
 
             // Apply preset target if available so we morph into preset's target, not default Obama
             if let Some(target) = &self.gui.current_preset_target {
                 settings.set_raw_target(target.clone());
             }
 
-            self.gui.show_progress_modal(settings.id);
             self.gui.saved_config = Some((img.clone(), settings.clone()));
 
             // Adjust proximity importance for consistency across resolutions
             settings.proximity_importance =
                 (settings.proximity_importance as f32 / (settings.sidelen as f32 / 128.0)) as i64;
 
-            self.gui
-                .process_cancelled
-                .store(false, std::sync::atomic::Ordering::Relaxed);
-
             let unprocessed = UnprocessedPreset {
                 name: settings.name.clone(),
                 width: img.width(),
@@ -615,27 +649,7 @@ impl App for VantaMorphApp {
                 source_img: img.into_raw(),
                 target_img: None,
             };
-
-            self.resize_textures(device, (settings.sidelen, settings.sidelen), false);
-
-            #[cfg(target_arch = "wasm32")]
-            {
-                self.start_job(unprocessed, settings);
-            }
-
-            #[cfg(not(target_arch = "wasm32"))]
-            {
-                std::thread::spawn({
-                    let mut tx = self.progress_tx.clone();
-                    let cancelled = self.gui.process_cancelled.clone();
-                    move || {
-                        let result = calculate::process(unprocessed, settings, &mut tx, cancelled);
-                        if let Err(err) = result {
-                            tx.send(ProgressMsg::Error(err.to_string())).ok();
-                        }
-                    }
-                });
-            }
+            self.gui.queue_job(unprocessed, settings, None);
         }
 
         // Increment frame counter for WASM worker initialization delay
@@ -661,6 +675,8 @@ impl App for VantaMorphApp {
 
                         let mut settings =
                             GenerationSettings::default(Uuid::new_v4(), preset.inner.name.clone());
+                        settings.sidelen = self.gui.resolution;
+                        settings.edge_boost = self.gui.edge_boost;
 
                         // Set the preset's target image
                         if let Some(target_data) = &preset.inner.target_img {
@@ -675,18 +691,12 @@ impl App for VantaMorphApp {
                             }
                         }
 
-                        self.gui.show_progress_modal(settings.id);
                         self.gui.saved_config = Some((source_img.clone(), settings.clone()));
-                        self.gui.replacing_preset_index = Some(preset_idx);
 
                         settings.proximity_importance = (settings.proximity_importance as f32
                             / (settings.sidelen as f32 / 128.0))
                             as i64;
 
-                        self.gui
-                            .process_cancelled
-                            .store(false, std::sync::atomic::Ordering::Relaxed);
-
                         let unprocessed = UnprocessedPreset {
                             name: settings.name.clone(),
                             width: source_img.width(),
@@ -694,32 +704,7 @@ impl App for VantaMorphApp {
                             source_img: source_img.into_raw(),
                             target_img: None,
                         };
-
-                        self.resize_textures(device, (settings.sidelen, settings.sidelen), false);
-
-                        #[cfg(target_arch = "wasm32")]
-                        {
-                            self.start_job(unprocessed, settings);
-                        }
-
-                        #[cfg(not(target_arch = "wasm32"))]
-                        {
-                            std::thread::spawn({
-                                let mut tx = self.progress_tx.clone();
-                                let cancelled = self.gui.process_cancelled.clone();
-                                move || {
-                                    let result = calculate::process(
-                                        unprocessed,
-                                        settings,
-                                        &mut tx,
-                                        cancelled,
-                                    );
-                                    if let Err(err) = result {
-                                        tx.send(ProgressMsg::Error(err.to_string())).ok();
-                                    }
-                                }
-                            });
-                        }
+                        self.gui.queue_job(unprocessed, settings, Some(preset.id));
                     }
                 }
             }
@@ -839,26 +824,23 @@ impl App for VantaMorphApp {
                     // Export button (primary action)
                     let export_btn = egui::Button::new(egui::RichText::new("📤 Export").strong())
                         .fill(egui::Color32::from_rgb(70, 130, 180));
-                    if ui.add(export_btn).on_hover_text("Export as GIF").clicked() {
-                        if !self.gif_recorder.is_recording() {
-                            self.gif_recorder.status = GifStatus::Recording;
-                            self.gif_recorder.encoder = None;
-                            if let Err(err) = self
-                                .gif_recorder
-                                .init_encoder(self.colors.read().unwrap().as_ref())
-                            {
-                                self.gif_recorder.status = GifStatus::Error(err.to_string());
-                            } else {
-                                self.resize_textures(
-                                    device,
-                                    (GIF_RESOLUTION, GIF_RESOLUTION),
-                                    false,
-                                );
-                                self.reset_sim(device, &rs.queue);
-                                self.gui.animate = true;
-                                for _ in 0..20 {
-                                    self.sim.update(&mut self.seeds, self.size.0);
-                                }
+                    if ui.add(export_btn).on_hover_text("Export as GIF").clicked()
+                        && !self.gif_recorder.is_recording()
+                    {
+                        self.gif_recorder.status = GifStatus::Recording;
+                        self.gif_recorder.encoder = None;
+                        let gif_size = self.sim_canvas(GIF_RESOLUTION);
+                        if let Err(err) = self
+                            .gif_recorder
+                            .init_encoder(self.colors.read().unwrap().as_ref(), gif_size)
+                        {
+                            self.gif_recorder.status = GifStatus::Error(err.to_string());
+                        } else {
+                            self.resize_textures(device, gif_size, false);
+                            self.reset_sim(device, &rs.queue);
+                            self.gui.animate = true;
+                            for _ in 0..20 {
+                                self.sim.update(&mut self.seeds, self.size);
                             }
                         }
                     }
@@ -956,11 +938,11 @@ impl App for VantaMorphApp {
                                     },
                                 );
                             }
-                            if self.gui.staged_source.is_some() {
-                                if ui.small_button("✕ Clear").clicked() {
-                                    self.gui.staged_source = None;
-                                    self.gui.staged_source_texture = None;
-                                }
+                            if self.gui.staged_source.is_some()
+                                && ui.small_button("✕ Clear").clicked()
+                            {
+                                self.gui.staged_source = None;
+                                self.gui.staged_source_texture = None;
                             }
                         });
                     });
@@ -1041,11 +1023,11 @@ impl App for VantaMorphApp {
                                     },
                                 );
                             }
-                            if self.gui.staged_target.is_some() {
-                                if ui.small_button("✕ Clear").clicked() {
-                                    self.gui.staged_target = None;
-                                    self.gui.staged_target_texture = None;
-                                }
+                            if self.gui.staged_target.is_some()
+                                && ui.small_button("✕ Clear").clicked()
+                            {
+                                self.gui.staged_target = None;
+                                self.gui.staged_target_texture = None;
                             }
                         });
                     });
@@ -1074,6 +1056,8 @@ impl App for VantaMorphApp {
                             // Create morph with source and optional custom target
                             let source_img = ensure_reasonable_size(source_img);
                             let mut settings = GenerationSettings::default(Uuid::new_v4(), name);
+                            settings.sidelen = self.gui.resolution;
+                            settings.edge_boost = self.gui.edge_boost;
 
                             // Use staged target if provided, otherwise use current preset's target
                             if let Some((_target_name, target_img)) = &self.gui.staged_target {
@@ -1083,17 +1067,12 @@ impl App for VantaMorphApp {
                                 settings.set_raw_target(target.clone());
                             }
 
-                            self.gui.show_progress_modal(settings.id);
                             self.gui.saved_config = Some((source_img.clone(), settings.clone()));
 
                             settings.proximity_importance = (settings.proximity_importance as f32
                                 / (settings.sidelen as f32 / 128.0))
                                 as i64;
 
-                            self.gui
-                                .process_cancelled
-                                .store(false, std::sync::atomic::Ordering::Relaxed);
-
                             let unprocessed = UnprocessedPreset {
                                 name: settings.name.clone(),
                                 width: source_img.width(),
@@ -1101,36 +1080,7 @@ impl App for VantaMorphApp {
                                 source_img: source_img.into_raw(),
                                 target_img: None,
                             };
-
-                            self.resize_textures(
-                                device,
-                                (settings.sidelen, settings.sidelen),
-                                false,
-                            );
-
-                            #[cfg(target_arch = "wasm32")]
-                            {
-                                self.start_job(unprocessed, settings);
-                            }
-
-                            #[cfg(not(target_arch = "wasm32"))]
-                            {
-                                std::thread::spawn({
-                                    let mut tx = self.progress_tx.clone();
-                                    let cancelled = self.gui.process_cancelled.clone();
-                                    move || {
-                                        let result = calculate::process(
-                                            unprocessed,
-                                            settings,
-                                            &mut tx,
-                                            cancelled,
-                                        );
-                                        if let Err(err) = result {
-                                            tx.send(ProgressMsg::Error(err.to_string())).ok();
-                                        }
-                                    }
-                                });
-                            }
+                            self.gui.queue_job(unprocessed, settings, None);
 
                             // Clear staged source (keep target if locked)
                             self.gui.staged_source_texture = None;
@@ -1141,6 +1091,30 @@ impl App for VantaMorphApp {
                         }
                     }
 
+                    if ui
+                        .add_enabled(has_source, egui::Button::new("⚙ Advanced…"))
+                        .on_hover_text("crop, weights and solver settings before starting")
+                        .clicked()
+                    {
+                        if let Some((name, source_img)) = self.gui.staged_source.take() {
+                            let source_img = ensure_reasonable_size(source_img);
+                            let mut settings = GenerationSettings::default(Uuid::new_v4(), name);
+                            settings.sidelen = self.gui.resolution;
+                            settings.edge_boost = self.gui.edge_boost;
+                            if let Some((_target_name, target_img)) = &self.gui.staged_target {
+                                let target_img = ensure_reasonable_size(target_img.clone());
+                                settings.set_raw_target(target_img);
+                            } else if let Some(target) = &self.gui.current_preset_target {
+                                settings.set_raw_target(target.clone());
+                            }
+                            self.gui.configuring_generation =
+                                Some((source_img, settings, GuiImageCache::default()));
+                            self.gui.staged_source_texture = None;
+                            #[cfg(target_arch = "wasm32")]
+                            hide_icons();
+                        }
+                    }
+
                     ui.add_space(8.0);
                     ui.separator();
 
@@ -1365,6 +1339,7 @@ impl App for VantaMorphApp {
                                 ui.selectable_value(&mut self.gui.resolution, 128, "128");
                                 ui.selectable_value(&mut self.gui.resolution, 256, "256");
                                 ui.selectable_value(&mut self.gui.resolution, 512, "512");
+                                ui.selectable_value(&mut self.gui.resolution, 1024, "1024");
                             });
 
                             ui.add_space(12.0);
@@ -1415,6 +1390,66 @@ impl App for VantaMorphApp {
                                 ui.label("Split Position:");
                                 ui.add(egui::Slider::new(&mut self.gui.split_position, 0.0..=1.0));
                             }
+
+                            // Analysis overlay
+                            ui.label("Analysis:");
+                            ui.horizontal_wrapped(|ui| {
+                                for view in [
+                                    AnalysisView::None,
+                                    AnalysisView::Cost,
+                                    AnalysisView::Displacement,
+                                    AnalysisView::Flow,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut self.gui.analysis.view,
+                                        view,
+                                        view.label(),
+                                    );
+                                }
+                            });
+                            if self.gui.analysis.view == AnalysisView::Cost
+                                && self.gui.presets[self.gui.current_preset].settings.is_none()
+                            {
+                                ui.label("cost is only known for morphs made since starting");
+                            }
+
+                            ui.add_space(12.0);
+                            ui.separator();
+                            ui.add_space(8.0);
+
+                            // Metrics of the shown preset
+                            ui.label("Result:");
+                            match &self.gui.presets[self.gui.current_preset].metrics {
+                                Some(metrics) => {
+                                    egui::Grid::new("metrics").num_columns(2).show(ui, |ui| {
+                                        ui.label("total cost");
+                                        ui.label(metrics.total_cost.to_string());
+                                        ui.end_row();
+                                        ui.label("mean displacement");
+                                        ui.label(format!("{:.1} cells", metrics.mean_displacement));
+                                        ui.end_row();
+                                        ui.label("max displacement");
+                                        ui.label(format!("{:.1} cells", metrics.max_displacement));
+                                        ui.end_row();
+                                        ui.label("PSNR");
+                                        ui.label(format!("{:.2} dB", metrics.psnr));
+                                        ui.end_row();
+                                        ui.label("SSIM");
+                                        ui.label(format!("{:.4}", metrics.ssim));
+                                        ui.end_row();
+                                        if metrics.reverted_cells > 0 {
+                                            ui.label("cells reverted").on_hover_text(
+                                                "moves the region doesn't allow, undone at the end",
+                                            );
+                                            ui.label(metrics.reverted_cells.to_string());
+                                            ui.end_row();
+                                        }
+                                    });
+                                }
+                                None => {
+                                    ui.label("only measured for morphs made here");
+                                }
+                            }
                         }
                     }
                 });
@@ -1456,6 +1491,11 @@ impl App for VantaMorphApp {
                                     }
                                 },
                             );
+                            if let Some((_, settings, cache)) =
+                                self.gui.configuring_generation.as_mut()
+                            {
+                                aspect_gui(ui, settings, cache);
+                            }
 
                             ui.separator();
 
@@ -1472,25 +1512,30 @@ impl App for VantaMorphApp {
                                     if let Some((source_img, settings, cache)) =
                                         self.gui.configuring_generation.as_mut()
                                     {
+                                        let target = settings.get_raw_target();
+                                        let preview_grid = settings.grid_at(128);
                                         change_source = image_crop_gui(
                                             "source",
                                             ui,
                                             source_img,
                                             &mut settings.source_crop_scale,
+                                            preview_grid,
                                             &mut cache.source_preview,
                                         );
                                         if is_landscape {
                                             // ./arrow-right.svg
                                             ui.vertical(|ui| {
-                                                image_overlap_preview(
-                                                    "overlap preview",
-                                                    ui,
-                                                    settings,
-                                                    cache,
-                                                    source_img,
-                                                    &settings.get_raw_target(),
-                                                    0.5,
-                                                );
+                                                if let Ok(target) = &target {
+                                                    image_overlap_preview(
+                                                        "overlap preview",
+                                                        ui,
+                                                        settings,
+                                                        cache,
+                                                        source_img,
+                                                        target,
+                                                        0.5,
+                                                    );
+                                                }
 
                                                 ui.add(
                                                     egui::Image::new(egui::include_image!(
@@ -1501,13 +1546,23 @@ impl App for VantaMorphApp {
                                             });
                                         }
 
-                                        change_target = image_crop_gui(
-                                            "target",
-                                            ui,
-                                            &settings.get_raw_target(),
-                                            &mut settings.target_crop_scale,
-                                            &mut cache.target_preview,
-                                        );
+                                        change_target = match &target {
+                                            Ok(target) => image_crop_gui(
+                                                "target",
+                                                ui,
+                                                target,
+                                                &mut settings.target_crop_scale,
+                                                preview_grid,
+                                                &mut cache.target_preview,
+                                            ),
+                                            Err(err) => {
+                                                ui.vertical(|ui| {
+                                                    ui.label(format!("error: {}", err));
+                                                    ui.button("choose target image").clicked()
+                                                })
+                                                .inner
+                                            }
+                                        };
                                     }
                                 },
                             );
@@ -1542,6 +1597,82 @@ impl App for VantaMorphApp {
                                 );
                             }
 
+                            // weight map, uploaded or painted over the target preview
+                            let mut change_weights = false;
+                            if let Some((_, settings, cache)) =
+                                self.gui.configuring_generation.as_mut()
+                            {
+                                ui.horizontal(|ui| {
+                                    ui.label("weight map:");
+                                    if settings.has_custom_weights() {
+                                        ui.label("custom");
+                                        if ui.button("clear").clicked() {
+                                            settings.clear_weights();
+                                            cache.weight_preview = None;
+                                            cache.overlap_preview = None;
+                                        }
+                                    } else if settings.has_custom_target() {
+                                        ui.label("uniform");
+                                    } else {
+                                        ui.label("default");
+                                    }
+                                    change_weights = ui
+                                        .button("choose")
+                                        .on_hover_text(
+                                            "greyscale image laid over the target, white \
+                                             areas are matched most carefully",
+                                        )
+                                        .clicked();
+                                });
+                                egui::CollapsingHeader::new("paint weights")
+                                    .default_open(false)
+                                    .show(ui, |ui| {
+                                        weight_paint_gui(
+                                            ui,
+                                            settings,
+                                            cache,
+                                            &mut self.gui.weight_brush,
+                                        );
+                                    });
+                                egui::CollapsingHeader::new(format!(
+                                    "pins ({})",
+                                    settings.pins.len()
+                                ))
+                                .id_salt("pins")
+                                .default_open(false)
+                                .show(ui, |ui| {
+                                    pin_gui(ui, settings, cache, &mut self.gui.pending_pin);
+                                });
+                                egui::CollapsingHeader::new("region mask")
+                                    .default_open(false)
+                                    .show(ui, |ui| {
+                                        mask_paint_gui(
+                                            ui,
+                                            settings,
+                                            cache,
+                                            &mut self.gui.mask_brush,
+                                        );
+                                    });
+                            }
+                            if change_weights {
+                                prompt_image(
+                                    "choose weight map",
+                                    self,
+                                    |_, img: SourceImg, app: &mut VantaMorphApp| {
+                                        let img = ensure_reasonable_size(img);
+                                        if let Some((_, settings, cache)) =
+                                            &mut app.gui.configuring_generation
+                                        {
+                                            settings.set_raw_weights(
+                                                image::DynamicImage::ImageRgb8(img).to_luma8(),
+                                            );
+                                            cache.weight_preview = None;
+                                            cache.overlap_preview = None;
+                                        }
+                                    },
+                                );
+                            }
+
                             ui.separator();
 
                             if let Some((_img, settings, _)) =
@@ -1559,8 +1690,9 @@ impl App for VantaMorphApp {
                                                     [slider_w, 20.0],
                                                     egui::Slider::new(
                                                         &mut settings.sidelen,
-                                                        64..=256,
+                                                        64..=1024,
                                                     )
+                                                    .logarithmic(true)
                                                     .text("resolution"),
                                                 );
 
@@ -1574,6 +1706,116 @@ impl App for VantaMorphApp {
                                                     .text("proximity importance"),
                                                 );
 
+                                                let slider_w = ui.available_width().min(260.0);
+                                                ui.add_sized(
+                                                    [slider_w, 20.0],
+                                                    egui::Slider::new(
+                                                        &mut settings.texture_importance,
+                                                        0..=4,
+                                                    )
+                                                    .text("texture importance"),
+                                                );
+
+                                                let slider_w = ui.available_width().min(260.0);
+                                                ui.add_sized(
+                                                    [slider_w, 20.0],
+                                                    egui::Slider::new(
+                                                        &mut settings.motion_coherence,
+                                                        0..=20,
+                                                    )
+                                                    .text("motion coherence"),
+                                                )
+                                                .on_hover_text(
+                                                    "keeps neighbouring cells travelling \
+                                                     together (fast, multiscale and sinkhorn \
+                                                     algorithms)",
+                                                );
+
+                                                ui.checkbox(&mut settings.edge_boost, "edge boost")
+                                                    .on_hover_text(
+                                                        "match the target's edges, detail and \
+                                                         middle more closely",
+                                                    );
+
+                                                ui.horizontal(|ui| {
+                                                    let mut limited =
+                                                        settings.max_displacement.is_some();
+                                                    if ui
+                                                        .checkbox(&mut limited, "max displacement")
+                                                        .on_hover_text(
+                                                            "no cell travels further than this, \
+                                                             for subtle transitions",
+                                                        )
+                                                        .changed()
+                                                    {
+                                                        settings.max_displacement =
+                                                            limited.then_some(8);
+                                                    }
+                                                    if let Some(max) =
+                                                        &mut settings.max_displacement
+                                                    {
+                                                        ui.add(
+                                                            egui::DragValue::new(max)
+                                                                .range(1..=settings.sidelen)
+                                                                .suffix(" cells"),
+                                                        );
+                                                    }
+                                                });
+
+                                                let cost_function = match settings.cost_function {
+                                                    CostFunction::Quartic => "strong proximity",
+                                                    CostFunction::Quadratic => "soft proximity",
+                                                };
+                                                egui::ComboBox::from_id_salt("cost_select")
+                                                    .selected_text(cost_function)
+                                                    .show_ui(ui, |ui| {
+                                                        ui.selectable_value(
+                                                            &mut settings.cost_function,
+                                                            CostFunction::Quartic,
+                                                            "strong proximity",
+                                                        );
+                                                        ui.selectable_value(
+                                                            &mut settings.cost_function,
+                                                            CostFunction::Quadratic,
+                                                            "soft proximity",
+                                                        );
+                                                    });
+
+                                                let color_space = match settings.color_space {
+                                                    ColorSpace::Rgb => "sRGB colours",
+                                                    ColorSpace::LinearRgb => "linear RGB colours",
+                                                    ColorSpace::Lab => "CIELAB colours",
+                                                    ColorSpace::Ciede2000 => "CIEDE2000 colours",
+                                                };
+                                                egui::ComboBox::from_id_salt("color_space_select")
+                                                    .selected_text(color_space)
+                                                    .show_ui(ui, |ui| {
+                                                        for (space, label) in [
+                                                            (ColorSpace::Rgb, "sRGB colours"),
+                                                            (
+                                                                ColorSpace::LinearRgb,
+                                                                "linear RGB colours",
+                                                            ),
+                                                            (ColorSpace::Lab, "CIELAB colours"),
+                                                            (
+                                                                ColorSpace::Ciede2000,
+                                                                "CIEDE2000 colours",
+                                                            ),
+                                                        ] {
+                                                            ui.selectable_value(
+                                                                &mut settings.color_space,
+                                                                space,
+                                                                label,
+                                                            );
+                                                        }
+                                                    })
+                                                    .response
+                                                    .on_hover_text(
+                                                        "how colour differences are measured. \
+                                                         CIELAB and CIEDE2000 follow human \
+                                                         perception, CIEDE2000 is the slowest",
+                                                    );
+
                                                 let mut algorithm = match settings.algorithm {
                                                     calculate::util::Algorithm::Optimal => {
                                                         "optimal algorithm"
@@ -1581,6 +1823,15 @@ impl App for VantaMorphApp {
                                                     calculate::util::Algorithm::Genetic => {
                                                         "fast algorithm"
                                                     }
+                                                    calculate::util::Algorithm::Auction => {
+                                                        "auction algorithm"
+                                                    }
+                                                    calculate::util::Algorithm::Multiscale => {
+                                                        "multiscale algorithm"
+                                                    }
+                                                    calculate::util::Algorithm::Sinkhorn => {
+                                                        "sinkhorn algorithm"
+                                                    }
                                                 };
 
                                                 egui::ComboBox::from_id_salt("algorithm_select")
@@ -1597,7 +1848,198 @@ impl App for VantaMorphApp {
                                                             settings.algorithm =
                                                                 calculate::util::Algorithm::Genetic;
                                                         }
+                                                        if ui.button("auction algorithm").clicked()
+                                                        {
+                                                            algorithm = "auction algorithm";
+                                                            settings.algorithm =
+                                                                calculate::util::Algorithm::Auction;
+                                                        }
+                                                        if ui
+                                                            .button("multiscale algorithm")
+                                                            .clicked()
+                                                        {
+                                                            algorithm = "multiscale algorithm";
+                                                            settings.algorithm =
+                                                                calculate::util::Algorithm::Multiscale;
+                                                        }
+                                                        if ui.button("sinkhorn algorithm").clicked()
+                                                        {
+                                                            algorithm = "sinkhorn algorithm";
+                                                            settings.algorithm =
+                                                                calculate::util::Algorithm::Sinkhorn;
+                                                        }
                                                     });
+
+                                                if let calculate::util::Algorithm::Sinkhorn =
+                                                    settings.algorithm
+                                                {
+                                                    let slider_w = ui.available_width().min(260.0);
+                                                    ui.add_sized(
+                                                        [slider_w, 20.0],
+                                                        egui::Slider::new(
+                                                            &mut settings.sinkhorn_epsilon,
+                                                            0.001..=0.1,
+                                                        )
+                                                        .logarithmic(true)
+                                                        .text("blur"),
+                                                    );
+                                                }
+
+                                                if let calculate::util::Algorithm::Genetic =
+                                                    settings.algorithm
+                                                {
+                                                    let mut anneal = settings.annealing.is_some();
+                                                    if ui
+                                                        .checkbox(&mut anneal, "annealing")
+                                                        .on_hover_text(
+                                                            "also accept some worse swaps early on \
+                                                             to escape local minima, slower",
+                                                        )
+                                                        .changed()
+                                                    {
+                                                        settings.annealing =
+                                                            anneal.then(Annealing::default);
+                                                    }
+                                                    if let Some(annealing) = &mut settings.annealing
+                                                    {
+                                                        let slider_w =
+                                                            ui.available_width().min(260.0);
+                                                        ui.add_sized(
+                                                            [slider_w, 20.0],
+                                                            egui::Slider::new(
+                                                                &mut annealing.temperature,
+                                                                0.01..=2.0,
+                                                            )
+                                                            .logarithmic(true)
+                                                            .text("temperature"),
+                                                        );
+                                                        ui.add_sized(
+                                                            [slider_w, 20.0],
+                                                            egui::Slider::new(
+                                                                &mut annealing.generations,
+                                                                50..=2000,
+                                                            )
+                                                            .logarithmic(true)
+                                                            .text("generations"),
+                                                        );
+                                                        ui.add_sized(
+                                                            [slider_w, 20.0],
+                                                            egui::Slider::new(
+                                                                &mut annealing.restarts,
+                                                                0..=5,
+                                                            )
+                                                            .text("restarts"),
+                                                        );
+                                                        ui.horizontal(|ui| {
+                                                            ui.label("cooling");
+                                                            ui.selectable_value(
+                                                                &mut annealing.cooling,
+                                                                Cooling::Geometric,
+                                                                "geometric",
+                                                            );
+                                                            ui.selectable_value(
+                                                                &mut annealing.cooling,
+                                                                Cooling::Linear,
+                                                                "linear",
+                                                            );
+                                                        });
+                                                    }
+                                                }
+
+                                                ui.horizontal(|ui| {
+                                                    ui.label("seed");
+                                                    ui.add(egui::DragValue::new(
+                                                        &mut settings.seed,
+                                                    ));
+                                                });
+
+                                                ui.horizontal(|ui| {
+                                                    let mut limited = settings.time_budget.is_some();
+                                                    if ui
+                                                        .checkbox(&mut limited, "time limit")
+                                                        .on_hover_text(
+                                                            "stop after this long and keep the \
+                                                             best result so far",
+                                                        )
+                                                        .changed()
+                                                    {
+                                                        settings.time_budget =
+                                                            limited.then_some(30.0);
+                                                    }
+                                                    if let Some(secs) = &mut settings.time_budget {
+                                                        ui.add(
+                                                            egui::DragValue::new(secs)
+                                                                .range(1.0..=3600.0)
+                                                                .suffix(" s"),
+                                                        );
+                                                    }
+                                                });
+                                                ui.horizontal(|ui| {
+                                                    let mut limited =
+                                                        settings.max_iterations.is_some();
+                                                    if ui
+                                                        .checkbox(&mut limited, "iteration limit")
+                                                        .on_hover_text(
+                                                            "stop after this many progress \
+                                                             updates and keep the best result so far",
+                                                        )
+                                                        .changed()
+                                                    {
+                                                        settings.max_iterations =
+                                                            limited.then_some(100);
+                                                    }
+                                                    if let Some(max) = &mut settings.max_iterations
+                                                    {
+                                                        ui.add(
+                                                            egui::DragValue::new(max)
+                                                                .range(1..=100_000),
+                                                        );
+                                                    }
+                                                });
+
+                                                let (width, height) = settings.grid();
+                                                let current = self
+                                                    .gui
+                                                    .presets
+                                                    .get(self.gui.current_preset)
+                                                    .filter(|preset| {
+                                                        preset.inner.width == width
+                                                            && preset.inner.height == height
+                                                    })
+                                                    .map(|preset| &preset.assignments)
+                                                    .filter(|a| {
+                                                        a.len() == (width * height) as usize
+                                                    });
+                                                if current.is_none() {
+                                                    settings.warm_start = None;
+                                                }
+                                                let mut warm = settings.warm_start.is_some();
+                                                if ui
+                                                    .add_enabled(
+                                                        current.is_some(),
+                                                        egui::Checkbox::new(
+                                                            &mut warm,
+                                                            "start from current morph",
+                                                        ),
+                                                    )
+                                                    .on_hover_text(
+                                                        "start from the loaded morph instead of \
+                                                         from scratch, needs the same resolution",
+                                                    )
+                                                    .changed()
+                                                {
+                                                    settings.warm_start =
+                                                        current.filter(|_| warm).cloned();
+                                                }
+
+                                                #[cfg(not(target_arch = "wasm32"))]
+                                                ui.checkbox(&mut settings.parallel, "use all cores")
+                                                    .on_hover_text(
+                                                        "swap in tiles on every core, a seed \
+                                                         gives the same result on any machine. \
+                                                         Swaps can't leave their tile, so the \
+                                                         result is a little worse",
+                                                    );
                                             },
                                         );
                                     });
@@ -1611,7 +2053,6 @@ impl App for VantaMorphApp {
                                     if let Some((img, mut settings, _)) =
                                         self.gui.configuring_generation.take()
                                     {
-                                        self.gui.show_progress_modal(settings.id);
                                         self.gui.saved_config =
                                             Some((img.clone(), settings.clone()));
                                         //self.gui.currently_processing = Some(path.clone());
@@ -1623,10 +2064,6 @@ impl App for VantaMorphApp {
                                                 / (settings.sidelen as f32 / 128.0))
                                                 as i64;
 
-                                        self.gui
-                                            .process_cancelled
-                                            .store(false, std::sync::atomic::Ordering::Relaxed);
-
                                         let unprocessed = UnprocessedPreset {
                                             name: settings.name.clone(),
                                             width: img.width(),
@@ -1634,43 +2071,16 @@ impl App for VantaMorphApp {
                                             source_img: img.into_raw(),
                                             target_img: None,
                                         };
-
-                                        self.resize_textures(
-                                            device,
-                                            (settings.sidelen, settings.sidelen),
-                                            false,
-                                        );
-
-                                        #[cfg(target_arch = "wasm32")]
-                                        {
-                                            self.start_job(unprocessed, settings);
-                                        }
-
-                                        #[cfg(not(target_arch = "wasm32"))]
-                                        {
-                                            std::thread::spawn({
-                                                let mut tx = self.progress_tx.clone();
-                                                let cancelled = self.gui.process_cancelled.clone();
-                                                move || {
-                                                    let result = calculate::process(
-                                                        unprocessed,
-                                                        settings,
-                                                        &mut tx,
-                                                        cancelled,
-                                                    );
-                                                    if let Err(err) = result {
-                                                        tx.send(ProgressMsg::Error(
-                                                            err.to_string(),
-                                                        ))
-                                                        .ok();
-                                                    }
-                                                }
-                                            });
-                                        }
+                                        self.gui.queue_job(unprocessed, settings, None);
                                     }
                                 }
                                 if ui.button("cancel").clicked() {
-                                    self.gui.configuring_generation = None;
+                                    // back to the staged source it was opened with
+                                    if let Some((img, settings, _)) =
+                                        self.gui.configuring_generation.take()
+                                    {
+                                        self.gui.staged_source = Some((settings.name, img));
+                                    }
                                     #[cfg(target_arch = "wasm32")]
                                     show_icons();
                                 }
@@ -1680,107 +2090,157 @@ impl App for VantaMorphApp {
                 });
         }
 
-        if let Some(progress_id) = self.gui.show_progress_modal {
-            Window::new(progress_id.to_string())
+        self.start_queued_jobs(device);
+        while let Some(JobMsg { id, msg }) = self.get_latest_msg() {
+            if let ProgressMsg::UpdateAssignments(assignments) = msg {
+                // only the drawing canvas sends these, it runs outside the job queue
+                self.sim.set_assignments(assignments, self.size);
+                continue;
+            }
+            let focused = self.gui.jobs.focused() == Some(id);
+            let Some(job) = self.gui.jobs.get_mut(id) else {
+                // left over from a job that was cancelled or already finished
+                continue;
+            };
+            // a job reads its messages on the web only when it pauses, so without shared flags it
+            // can finish between the cancel and its next pause
+            let msg = match msg {
+                ProgressMsg::Done(_) if job.control.cancelled() => ProgressMsg::Cancelled,
+                msg => msg,
+            };
+            match msg {
+                ProgressMsg::Done(new_preset) => {
+                    let replacing = job
+                        .replacing
+                        .and_then(|id| self.gui.presets.iter().position(|p| p.id == id));
+                    self.gui.remove_job(id);
+                    self.gui.analysis.invalidate();
+
+                    // Replace existing preset or add new one
+                    let preset_index = if let Some(idx) = replacing {
+                        self.gui.presets[idx] = new_preset.clone();
+                        idx
+                    } else {
+                        self.gui.presets.push(new_preset.clone());
+                        self.gui.presets.len() - 1
+                    };
+
+                    // a job finishing in the background leaves the focused job's preview be
+                    if focused || self.gui.jobs.running().next().is_none() {
+                        self.preview_image = None;
+                        self.resize_textures(device, self.sim_canvas(DEFAULT_RESOLUTION), false);
+                        self.change_sim(device, &rs.queue, new_preset, preset_index);
+                        self.gui.animate = true;
+                    }
+                    self.gui.has_morphed_once = true;
+                }
+                ProgressMsg::Progress(p) => {
+                    job.progress = p;
+                }
+                ProgressMsg::Error(err) => {
+                    job.state = JobState::Failed(err);
+                }
+                ProgressMsg::UpdatePreview {
+                    width,
+                    height,
+                    data,
+                } => {
+                    if focused {
+                        self.preview_image = image::ImageBuffer::from_vec(width, height, data);
+                    }
+                }
+                ProgressMsg::Cancelled => {
+                    self.gui.remove_job(id);
+                    if focused {
+                        self.preview_image = None;
+                        self.resize_textures(device, self.sim_canvas(DEFAULT_RESOLUTION), false);
+                    }
+                }
+                ProgressMsg::UpdateAssignments(_) => unreachable!(),
+            }
+        }
+
+        if !self.gui.jobs.is_empty() {
+            Window::new("jobs")
                 .title_bar(false)
                 .collapsible(false)
                 .movable(false)
                 .resizable(false)
                 .anchor(egui::Align2::CENTER_BOTTOM, (0.0, 0.0))
                 .show(ctx, |ui| {
-                    let processing_label_message = "processing...";
                     ui.vertical(|ui| {
                         ui.set_min_width(ui.available_width().min(400.0));
-                        while let Some(msg) = self.get_latest_msg() {
-                            match msg {
-                                ProgressMsg::Done(new_preset) => {
-                                    self.preview_image = None;
-                                    self.resize_textures(
-                                        device,
-                                        (DEFAULT_RESOLUTION, DEFAULT_RESOLUTION),
-                                        false,
-                                    );
+                        #[cfg(not(target_arch = "wasm32"))]
+                        ui.horizontal(|ui| {
+                            ui.label("run at once");
+                            ui.add(
+                                egui::DragValue::new(&mut self.gui.jobs.max_running).range(1..=8),
+                            );
+                        });
 
-                                    // Replace existing preset or add new one
-                                    let preset_index =
-                                        if let Some(idx) = self.gui.replacing_preset_index.take() {
-                                            self.gui.presets[idx] = new_preset.clone();
-                                            idx
-                                        } else {
-                                            self.gui.presets.push(new_preset.clone());
-                                            self.gui.presets.len() - 1
-                                        };
+                        let focused = self.gui.jobs.focused();
+                        let mut cancelled = None;
+                        let mut finished = None;
+                        let mut closed = None;
+                        for job in self.gui.jobs.iter() {
+                            ui.separator();
+                            ui.push_id(job.id, |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.label(egui::RichText::new(&job.name).strong());
+                                    ui.label(format!("{}×{}", job.grid.0, job.grid.1));
+                                    if focused == Some(job.id) {
+                                        ui.label("(preview)");
+                                    }
+                                });
 
-                                    self.change_sim(device, &rs.queue, new_preset, preset_index);
-                                    self.gui.animate = true;
-                                    self.gui.has_morphed_once = true;
-                                    self.gui.hide_progress_modal();
-                                    ui.close();
-                                    break;
-                                }
-                                ProgressMsg::Progress(p) => {
-                                    self.gui.last_progress = p;
-                                }
-                                ProgressMsg::Error(err) => {
+                                if let JobState::Failed(err) = &job.state {
                                     ui.label(format!("error: {}", err));
                                     if ui.button("close").clicked() {
-                                        ui.close();
+                                        closed = Some(job.id);
                                     }
+                                    return;
                                 }
-                                ProgressMsg::UpdatePreview {
-                                    width,
-                                    height,
-                                    data,
-                                } => {
-                                    let image = image::ImageBuffer::from_vec(width, height, data);
-                                    self.preview_image = image;
-                                }
-                                ProgressMsg::Cancelled => {
-                                    self.preview_image = None;
-                                    self.resize_textures(
-                                        device,
-                                        (DEFAULT_RESOLUTION, DEFAULT_RESOLUTION),
-                                        false,
-                                    );
-                                    self.gui.hide_progress_modal();
-                                    ui.close();
-                                }
-                                ProgressMsg::UpdateAssignments(assignments) => {
-                                    self.sim.set_assignments(assignments, self.size.0)
-                                }
-                            }
-                        }
 
-                        if self.gui.process_cancelled.load(Ordering::Relaxed) {
-                            ui.label("cancelling...");
-                        } else if self.gui.last_progress == 0.0 {
-                            ui.label("preparing...");
-                        } else {
-                            ui.label(processing_label_message);
-                        }
-                        ui.add(egui::ProgressBar::new(self.gui.last_progress).show_percentage());
+                                let status = match job.state {
+                                    JobState::Queued => "queued",
+                                    _ if job.progress == 0.0 => "preparing...",
+                                    _ => "processing...",
+                                };
+                                let status = if job.control.cancelled() {
+                                    "cancelling..."
+                                } else if job.control.finishing() {
+                                    "finishing..."
+                                } else {
+                                    status
+                                };
+                                ui.label(status);
+                                ui.add(egui::ProgressBar::new(job.progress).show_percentage());
 
-                        ui.horizontal(|ui| {
-                            if ui.button("cancel").clicked() {
-                                #[cfg(target_arch = "wasm32")]
-                                {
-                                    if let Some(w) = &self.worker {
-                                        w.terminate();
+                                ui.horizontal(|ui| {
+                                    if ui.button("cancel").clicked() {
+                                        cancelled = Some(job.id);
                                     }
-                                    self.worker = None;
-                                    self.preview_image = None;
-                                    self.resize_textures(
-                                        device,
-                                        (DEFAULT_RESOLUTION, DEFAULT_RESOLUTION),
-                                        false,
-                                    );
-                                    self.gui.hide_progress_modal();
-                                    ui.close();
-                                }
-                                self.gui.process_cancelled.store(true, Ordering::Relaxed);
-                                self.gui.last_progress = 0.0;
-                            }
-                        })
+                                    if job.state == JobState::Running
+                                        && ui
+                                            .button("finish now")
+                                            .on_hover_text("stop and keep the best result so far")
+                                            .clicked()
+                                    {
+                                        finished = Some(job.id);
+                                    }
+                                });
+                            });
+                        }
+
+                        if let Some(id) = closed {
+                            self.gui.remove_job(id);
+                        }
+                        if let Some(id) = cancelled {
+                            self.cancel_job(id);
+                        }
+                        if let Some(id) = finished {
+                            self.finish_job(id);
+                        }
                     });
                 });
         } else if !self.gif_recorder.not_recording() {
@@ -2042,10 +2502,7 @@ impl App for VantaMorphApp {
             });
 
             // Use egui's built-in file drop handling
-            if self.gui.pending_auto_morph.is_none()
-                && self.gui.show_progress_modal.is_none()
-                && self.gui.configuring_generation.is_none()
-            {
+            if self.gui.pending_auto_morph.is_none() && self.gui.configuring_generation.is_none() {
                 ctx.input(|i| {
                     if !i.raw.dropped_files.is_empty() {
                         for file in &i.raw.dropped_files {
@@ -2095,34 +2552,35 @@ impl App for VantaMorphApp {
         // === HANDLE KEYBOARD SHORTCUTS ===
         ctx.input(|i| {
             // Space = Play/Pause
-            if i.key_pressed(egui::Key::Space) && self.gui.show_progress_modal.is_none() {
+            if i.key_pressed(egui::Key::Space) && self.gui.jobs.is_empty() {
                 self.gui.animate = !self.gui.animate;
                 if self.gui.animate {
                     self.sim.prepare_play(&mut self.seeds, self.reverse);
                 }
             }
             // R = Reverse
-            if i.key_pressed(egui::Key::R) && self.gui.show_progress_modal.is_none() {
+            if i.key_pressed(egui::Key::R) && self.gui.jobs.is_empty() {
                 self.reverse = !self.reverse;
                 self.sim.prepare_play(&mut self.seeds, self.reverse);
                 self.gui.animate = true;
             }
             // L = Loop toggle
-            if i.key_pressed(egui::Key::L) && self.gui.show_progress_modal.is_none() {
+            if i.key_pressed(egui::Key::L) && self.gui.jobs.is_empty() {
                 self.gui.loop_playback = !self.gui.loop_playback;
             }
             // P = Toggle panels (Pro mode)
-            if i.key_pressed(egui::Key::P) && self.gui.show_progress_modal.is_none() {
-                if self.gui.ui_mode == UiMode::Pro {
-                    // Toggle both panels
-                    let both_visible = self.gui.show_left_panel && self.gui.show_right_panel;
-                    self.gui.show_left_panel = !both_visible;
-                    self.gui.show_right_panel = !both_visible;
-                }
+            if i.key_pressed(egui::Key::P)
+                && self.gui.jobs.is_empty()
+                && self.gui.ui_mode == UiMode::Pro
+            {
+                // Toggle both panels
+                let both_visible = self.gui.show_left_panel && self.gui.show_right_panel;
+                self.gui.show_left_panel = !both_visible;
+                self.gui.show_right_panel = !both_visible;
             }
             // Tab = Cycle right panel tabs (Pro mode)
             if i.key_pressed(egui::Key::Tab)
-                && self.gui.show_progress_modal.is_none()
+                && self.gui.jobs.is_empty()
                 && self.gui.ui_mode == UiMode::Pro
             {
                 self.gui.right_panel_tab = match self.gui.right_panel_tab {
@@ -2146,7 +2604,16 @@ impl App for VantaMorphApp {
                             let full = ui.available_size();
                             let aspect = self.size.0 as f32 / self.size.1 as f32;
                             let desired = full.x.min(full.y) * egui::vec2(1.0, aspect);
-                            ui.add(egui::Image::new((id, desired)).maintain_aspect_ratio(true));
+                            let canvas =
+                                ui.add(egui::Image::new((id, desired)).maintain_aspect_ratio(true));
+                            if matches!(self.gui.mode, GuiMode::Transform) {
+                                self.gui.analysis.paint(
+                                    ui,
+                                    canvas.rect,
+                                    &self.gui.presets,
+                                    self.gui.current_preset,
+                                );
+                            }
 
                             #[cfg(not(target_arch = "wasm32"))]
                             if matches!(self.gui.mode, GuiMode::Draw) {
@@ -2460,7 +2927,8 @@ fn prompt_image(
 }
 
 fn ensure_reasonable_size(img: SourceImg) -> SourceImg {
-    let max_side = 512;
+    // enough detail for the largest grid
+    let max_side = 1024;
     let (w, h) = img.dimensions();
     if w <= max_side && h <= max_side {
         return img;
@@ -2481,31 +2949,340 @@ fn image_overlap_preview(
     get_raw_target: &SourceImg,
     blend: f32,
 ) {
-    let tex = if cache.overlap_preview.is_none()
-        || cache.source_preview.is_none()
-        || cache.target_preview.is_none()
+    let tex = if let Some(p) = cache
+        .overlap_preview
+        .as_ref()
+        .filter(|_| cache.source_preview.is_some() && cache.target_preview.is_some())
     {
-        let src_img = settings.source_crop_scale.apply(source_img, 64);
-        let tgt_img = settings.target_crop_scale.apply(get_raw_target, 64);
-        let blended = blend_rgb_images(&src_img, &tgt_img, blend);
+        p.clone()
+    } else {
+        let grid = settings.grid_at(64);
+        let src_img = settings.source_crop_scale.apply(source_img, grid);
+        let tgt_img = settings.target_crop_scale.apply(get_raw_target, grid);
+        let mut blended = blend_rgb_images(&src_img, &tgt_img, blend);
+        if settings.has_custom_weights() {
+            if let Ok((_, weights)) = settings.target_at(grid) {
+                shade_by_weights(&mut blended, &weights);
+            }
+        }
         let p = ui.ctx().load_texture(
             arg,
-            egui::ColorImage::from_rgb([64, 64], blended.as_raw()),
+            egui::ColorImage::from_rgb([grid.0 as usize, grid.1 as usize], blended.as_raw()),
             egui::TextureOptions::LINEAR,
         );
         cache.overlap_preview = Some(p.clone());
         p
-    } else {
-        cache.overlap_preview.as_ref().unwrap().clone()
     };
     ui.add(egui::Image::from_texture(&tex));
 }
 
+/// Darkens each pixel of `img` by how little its cell's weight counts.
+fn shade_by_weights(img: &mut SourceImg, weights: &[i64]) {
+    for (pixel, &weight) in img.pixels_mut().zip(weights) {
+        let k = 0.25 + 0.75 * weight.clamp(0, 255) as f32 / 255.0;
+        for c in pixel.0.iter_mut() {
+            *c = (*c as f32 * k).round() as u8;
+        }
+    }
+}
+
+/// The cropped target shaded by its weights, dragging over it paints them with `brush`.
+fn weight_paint_gui(
+    ui: &mut egui::Ui,
+    settings: &mut GenerationSettings,
+    cache: &mut GuiImageCache,
+    brush: &mut WeightBrush,
+) {
+    const SIDE: u32 = 128;
+    let slider_w = ui.available_width().min(260.0);
+    ui.add_sized(
+        [slider_w, 20.0],
+        egui::Slider::new(&mut brush.size, 0.01..=0.3).text("brush size"),
+    );
+    ui.add_sized(
+        [slider_w, 20.0],
+        egui::Slider::new(&mut brush.strength, 0.05..=1.0).text("strength"),
+    );
+    ui.checkbox(&mut brush.erase, "erase")
+        .on_hover_text("lower the weights, for the parts that don't matter");
+
+    let tex = match cache
+        .weight_preview
+        .as_ref()
+        .filter(|_| cache.target_preview.is_some())
+    {
+        Some(t) => t.clone(),
+        None => {
+            let grid = settings.grid_at(SIDE);
+            let (mut target, weights) = match settings.target_at(grid) {
+                Ok(target) => target,
+                Err(err) => {
+                    ui.label(format!("error: {}", err));
+                    return;
+                }
+            };
+            shade_by_weights(&mut target, &weights);
+            let t = ui.ctx().load_texture(
+                "weight preview",
+                egui::ColorImage::from_rgb([grid.0 as usize, grid.1 as usize], target.as_raw()),
+                egui::TextureOptions::LINEAR,
+            );
+            cache.weight_preview = Some(t.clone());
+            t
+        }
+    };
+    let response = ui.add(egui::Image::from_texture(&tex).sense(egui::Sense::drag()));
+    let rect = response.rect;
+    if let Some(pos) = response.hover_pos() {
+        ui.painter().circle_stroke(
+            pos,
+            brush.size * rect.width().min(rect.height()),
+            egui::Stroke::new(1.0, Color32::WHITE),
+        );
+    }
+    let Some(pos) = response
+        .interact_pointer_pos()
+        .filter(|_| response.is_pointer_button_down_on())
+    else {
+        return;
+    };
+    let Ok((w, h)) = settings.get_raw_target().map(|t| t.dimensions()) else {
+        return;
+    };
+    // from the cropped preview back to the whole target
+    let (x0, y0, cw, ch) = settings.target_crop_scale.crop_rect(w, h, settings.grid());
+    let u = ((pos.x - rect.min.x) / rect.width()).clamp(0.0, 1.0);
+    let v = ((pos.y - rect.min.y) / rect.height()).clamp(0.0, 1.0);
+    let center = (
+        (x0 as f32 + u * cw as f32) / w as f32,
+        (y0 as f32 + v * ch as f32) / h as f32,
+    );
+    let radius = brush.size * cw.min(ch) as f32 / w.min(h) as f32;
+    let strength = (brush.strength * ui.input(|i| i.stable_dt) * 4.0).min(1.0);
+    if settings
+        .paint_weights(center, radius, strength, brush.erase)
+        .is_ok()
+    {
+        cache.weight_preview = None;
+        cache.overlap_preview = None;
+    }
+}
+
+/// The cropped target dimmed outside the region mask, dragging over it paints the mask with
+/// `brush`.
+fn mask_paint_gui(
+    ui: &mut egui::Ui,
+    settings: &mut GenerationSettings,
+    cache: &mut GuiImageCache,
+    brush: &mut WeightBrush,
+) {
+    const SIDE: u32 = 128;
+    ui.horizontal(|ui| {
+        ui.label(if settings.has_mask() {
+            "only the bright part morphs"
+        } else {
+            "everything morphs, paint to pick a part"
+        });
+        if settings.has_mask() && ui.button("clear").clicked() {
+            settings.clear_mask();
+            cache.mask_preview = None;
+        }
+    });
+    let slider_w = ui.available_width().min(260.0);
+    ui.add_sized(
+        [slider_w, 20.0],
+        egui::Slider::new(&mut brush.size, 0.01..=0.3).text("brush size"),
+    );
+    ui.checkbox(&mut brush.erase, "erase")
+        .on_hover_text("take cells out of the region, they stay where they are");
+
+    let tex = match cache
+        .mask_preview
+        .as_ref()
+        .filter(|_| cache.target_preview.is_some())
+    {
+        Some(t) => t.clone(),
+        None => {
+            let grid = settings.grid_at(SIDE);
+            let mut target = match settings.get_raw_target() {
+                Ok(raw) => settings.target_crop_scale.apply(&raw, grid),
+                Err(err) => {
+                    ui.label(format!("error: {}", err));
+                    return;
+                }
+            };
+            if let Ok(Some(inside)) = settings.mask_at(grid) {
+                for (pixel, inside) in target.pixels_mut().zip(inside) {
+                    if !inside {
+                        for c in pixel.0.iter_mut() {
+                            *c /= 4;
+                        }
+                    }
+                }
+            }
+            let t = ui.ctx().load_texture(
+                "mask preview",
+                egui::ColorImage::from_rgb([grid.0 as usize, grid.1 as usize], target.as_raw()),
+                egui::TextureOptions::LINEAR,
+            );
+            cache.mask_preview = Some(t.clone());
+            t
+        }
+    };
+    let response = ui.add(egui::Image::from_texture(&tex).sense(egui::Sense::drag()));
+    let rect = response.rect;
+    if let Some(pos) = response.hover_pos() {
+        ui.painter().circle_stroke(
+            pos,
+            brush.size * rect.width().min(rect.height()),
+            egui::Stroke::new(1.0, Color32::WHITE),
+        );
+    }
+    let Some(pos) = response
+        .interact_pointer_pos()
+        .filter(|_| response.is_pointer_button_down_on())
+    else {
+        return;
+    };
+    let center = (
+        ((pos.x - rect.min.x) / rect.width()).clamp(0.0, 1.0),
+        ((pos.y - rect.min.y) / rect.height()).clamp(0.0, 1.0),
+    );
+    if settings.paint_mask(center, brush.size, brush.erase).is_ok() {
+        cache.mask_preview = None;
+    }
+}
+
+/// Picks the grid's shape, both images are cropped to it.
+fn aspect_gui(ui: &mut egui::Ui, settings: &mut GenerationSettings, cache: &mut GuiImageCache) {
+    let before = settings.aspect;
+    let label = |(w, h): (u32, u32)| format!("{w}:{h}");
+    ui.horizontal(|ui| {
+        ui.label("aspect:");
+        egui::ComboBox::from_id_salt("aspect_select")
+            .selected_text(label(settings.aspect))
+            .show_ui(ui, |ui| {
+                for aspect in ASPECTS {
+                    ui.selectable_value(&mut settings.aspect, aspect, label(aspect));
+                }
+            });
+        if ui
+            .button("like target")
+            .on_hover_text("use the target image's shape, so none of it is cropped away")
+            .clicked()
+        {
+            if let Ok((w, h)) = settings.get_raw_target().map(|t| t.dimensions()) {
+                let (mut a, mut b) = (w, h);
+                while b != 0 {
+                    (a, b) = (b, a % b);
+                }
+                settings.aspect = (w / a.max(1), h / a.max(1));
+            }
+        }
+        let (w, h) = settings.grid();
+        ui.label(format!("{w}×{h} cells"));
+    });
+    if settings.aspect != before {
+        *cache = GuiImageCache::default();
+    }
+}
+
+/// The cropped source and target side by side, clicking one then the other adds a pin.
+fn pin_gui(
+    ui: &mut egui::Ui,
+    settings: &mut GenerationSettings,
+    cache: &GuiImageCache,
+    pending: &mut Option<(f32, f32)>,
+) {
+    let (Some(source), Some(target)) = (&cache.source_preview, &cache.target_preview) else {
+        return;
+    };
+    ui.label(match pending {
+        None => "click a point on the source",
+        Some(_) => "now click where it should end up on the target",
+    });
+
+    let fraction = |rect: egui::Rect, pos: egui::Pos2| {
+        (
+            ((pos.x - rect.min.x) / rect.width()).clamp(0.0, 1.0),
+            ((pos.y - rect.min.y) / rect.height()).clamp(0.0, 1.0),
+        )
+    };
+    let at = |rect: egui::Rect, (x, y): (f32, f32)| rect.min + egui::vec2(x, y) * rect.size();
+    let (source_rect, target_rect) = ui
+        .horizontal(|ui| {
+            let s = ui.add(egui::Image::from_texture(source).sense(egui::Sense::click()));
+            if let Some(pos) = s.interact_pointer_pos().filter(|_| s.clicked()) {
+                *pending = Some(fraction(s.rect, pos));
+            }
+            let t = ui.add(egui::Image::from_texture(target).sense(egui::Sense::click()));
+            if let (Some(pos), Some(source)) =
+                (t.interact_pointer_pos().filter(|_| t.clicked()), *pending)
+            {
+                settings.pins.push(Pin {
+                    source,
+                    target: fraction(t.rect, pos),
+                });
+                *pending = None;
+            }
+            (s.rect, t.rect)
+        })
+        .inner;
+
+    let painter = ui.painter();
+    for (i, pin) in settings.pins.iter().enumerate() {
+        let color = Color32::from(egui::epaint::Hsva::new(
+            i as f32 * 0.618 % 1.0,
+            0.8,
+            1.0,
+            1.0,
+        ));
+        for pos in [at(source_rect, pin.source), at(target_rect, pin.target)] {
+            painter.circle(pos, 5.0, color, egui::Stroke::new(1.0, Color32::BLACK));
+            painter.text(
+                pos,
+                egui::Align2::CENTER_CENTER,
+                (i + 1).to_string(),
+                egui::FontId::proportional(8.0),
+                Color32::BLACK,
+            );
+        }
+    }
+    if let Some(source) = *pending {
+        painter.circle_stroke(
+            at(source_rect, source),
+            5.0,
+            egui::Stroke::new(2.0, Color32::WHITE),
+        );
+    }
+
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(
+                pending.is_some() || !settings.pins.is_empty(),
+                egui::Button::new("undo"),
+            )
+            .clicked()
+            && pending.take().is_none()
+        {
+            settings.pins.pop();
+        }
+        if ui
+            .add_enabled(!settings.pins.is_empty(), egui::Button::new("clear"))
+            .clicked()
+        {
+            settings.pins.clear();
+            *pending = None;
+        }
+    });
+}
+
 fn image_crop_gui(
     name: &'static str,
     ui: &mut egui::Ui,
     img: &SourceImg,
     crop_scale: &mut CropScale,
+    grid: (u32, u32),
     cache: &mut Option<TextureHandle>,
 ) -> bool {
     let mut open_file_dialog = false;
@@ -2514,7 +3291,10 @@ fn image_crop_gui(
             None => {
                 let p = ui.ctx().load_texture(
                     name,
-                    egui::ColorImage::from_rgb([128, 128], crop_scale.apply(img, 128).as_raw()),
+                    egui::ColorImage::from_rgb(
+                        [grid.0 as usize, grid.1 as usize],
+                        crop_scale.apply(img, grid).as_raw(),
+                    ),
                     egui::TextureOptions::LINEAR,
                 );
                 *cache = Some(p.clone());
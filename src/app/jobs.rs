@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use egui_wgpu::wgpu;
+use uuid::Uuid;
+
+use crate::VantaMorphApp;
+use crate::app::calculate::error::CalculateError;
+use crate::app::calculate::util::{GenerationSettings, JobControl};
+#[cfg(target_arch = "wasm32")]
+use crate::app::calculate::worker::WorkerReq;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::app::calculate::{
+    self, ProgressMsg,
+    util::{JobSink, ProgressSink},
+};
+use crate::app::preset::UnprocessedPreset;
+
+/// How many morphs run at once by default. Every solver already keeps a core busy, the swap
+/// solvers all of them, so more mostly splits the same time between jobs.
+#[cfg(not(target_arch = "wasm32"))]
+pub const DEFAULT_MAX_RUNNING: usize = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobState {
+    Queued,
+    Running,
+    Failed(CalculateError),
+}
+
+pub struct Job {
+    pub id: Uuid,
+    pub name: String,
+    /// Cells across and down.
+    pub grid: (u32, u32),
+    pub state: JobState,
+    pub progress: f32,
+    /// `Preset::id` of the preset the result replaces, added as a new preset if `None` or if
+    /// that preset is gone by the time it's done.
+    pub replacing: Option<Uuid>,
+    pub control: Arc<JobControl>,
+    // taken when the job starts
+    request: Option<(UnprocessedPreset, GenerationSettings)>,
+}
+
+/// Morphs waiting for or running on a solver thread, or the worker on the web. Messages are
+/// matched to jobs by `GenerationSettings::id`, anything for a job that is no longer here is
+/// stale and gets dropped.
+pub struct JobQueue {
+    jobs: Vec<Job>,
+    /// Jobs allowed to run at the same time. The web worker runs one job at a time.
+    pub max_running: usize,
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self {
+            jobs: vec![],
+            #[cfg(not(target_arch = "wasm32"))]
+            max_running: DEFAULT_MAX_RUNNING,
+            #[cfg(target_arch = "wasm32")]
+            max_running: 1,
+        }
+    }
+}
+
+impl JobQueue {
+    pub fn push(
+        &mut self,
+        source: UnprocessedPreset,
+        settings: GenerationSettings,
+        replacing: Option<Uuid>,
+    ) {
+        self.jobs.push(Job {
+            id: settings.id,
+            name: settings.name.clone(),
+            grid: settings.grid(),
+            state: JobState::Queued,
+            progress: 0.0,
+            replacing,
+            control: Arc::default(),
+            request: Some((source, settings)),
+        });
+    }
+
+    /// Marks the oldest queued job running and hands out its request, if fewer than
+    /// `max_running` jobs are running.
+    pub fn next_to_start(&mut self) -> Option<(UnprocessedPreset, GenerationSettings)> {
+        let running = self.running().count();
+        if running >= self.max_running {
+            return None;
+        }
+        let job = self.jobs.iter_mut().find(|j| j.state == JobState::Queued)?;
+        job.state = JobState::Running;
+        job.request.take()
+    }
+
+    pub fn running(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter().filter(|j| j.state == JobState::Running)
+    }
+
+    /// The job whose previews are shown, the oldest running one.
+    pub fn focused(&self) -> Option<Uuid> {
+        self.running().next().map(|j| j.id)
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<&Job> {
+        self.jobs.iter().find(|j| j.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: Uuid) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|j| j.id == id)
+    }
+
+    pub fn remove(&mut self, id: Uuid) {
+        self.jobs.retain(|j| j.id != id);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+}
+
+impl VantaMorphApp {
+    /// Starts queued jobs while fewer than `max_running` are running, each on its own thread, or
+    /// on the web worker.
+    pub fn start_queued_jobs(&mut self, device: &wgpu::Device) {
+        #[cfg(target_arch = "wasm32")]
+        if self.worker.is_none() {
+            return;
+        }
+        while let Some((source, settings)) = self.gui.jobs.next_to_start() {
+            // previews come from the oldest running job, shown at its resolution
+            if self.gui.jobs.focused() == Some(settings.id) {
+                self.resize_textures(device, settings.grid(), false);
+            }
+
+            let control = self.gui.jobs.get(settings.id).unwrap().control.clone();
+
+            #[cfg(target_arch = "wasm32")]
+            self.start_job(source, settings, &control);
+
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                let mut tx = JobSink {
+                    id: settings.id,
+                    tx: self.progress_tx.clone(),
+                };
+                std::thread::spawn(move || {
+                    let result =
+                        pollster::block_on(calculate::process(source, settings, &mut tx, control));
+                    if let Err(err) = result {
+                        tx.send(ProgressMsg::Error(err));
+                    }
+                });
+            }
+        }
+    }
+
+    /// Stops a job. A queued job is just dropped, a running one is told to stop and leaves the
+    /// list once it has. On the web the worker is also sent the request, for when the flags
+    /// aren't shared with it.
+    pub fn cancel_job(&mut self, id: Uuid) {
+        let Some(job) = self.gui.jobs.get(id) else {
+            return;
+        };
+        if job.state != JobState::Running {
+            self.gui.remove_job(id);
+            return;
+        }
+
+        job.control.cancel();
+        #[cfg(target_arch = "wasm32")]
+        self.send_to_worker(WorkerReq::Cancel { id });
+    }
+
+    /// Has a running job stop and hand in the best result it found so far.
+    pub fn finish_job(&mut self, id: Uuid) {
+        let Some(job) = self.gui.jobs.get(id) else {
+            return;
+        };
+        job.control.finish();
+        #[cfg(target_arch = "wasm32")]
+        self.send_to_worker(WorkerReq::Finish { id });
+    }
+}
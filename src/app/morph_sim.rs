@@ -8,7 +8,9 @@ use crate::app::{SeedColor, SeedPos, preset::Preset};
 use crate::app::preset::UnprocessedPreset;
 
 // const DST_FORCE: f32 = 0.2;
-pub fn init_image(sidelen: u32, source: Preset) -> (u32, Vec<SeedPos>, Vec<SeedColor>, Sim) {
+/// Seeds, colours and simulation for `source` on a canvas of `size` pixels, which should have
+/// the shape of the preset's grid.
+pub fn init_image(size: (u32, u32), source: Preset) -> (u32, Vec<SeedPos>, Vec<SeedColor>, Sim) {
     let imgpath = image::ImageBuffer::from_vec(
         source.inner.width,
         source.inner.height,
@@ -17,11 +19,13 @@ pub fn init_image(sidelen: u32, source: Preset) -> (u32, Vec<SeedPos>, Vec<SeedC
     .unwrap();
     let assignments = source.assignments;
 
-    let (seeds, colors, seeds_n) = init_colors(sidelen, imgpath);
-    let mut sim = Sim::new(source.inner.name);
+    let grid = imgpath.dimensions();
+    let (seeds, colors, seeds_n) = init_colors(size, imgpath);
+    let mut sim = Sim::new(source.inner.name, grid);
     sim.cells = vec![CellBody::new(0.0, 0.0, 0.0, 0.0, 0.0); seeds_n];
 
-    sim.set_assignments(assignments, sidelen);
+    sim.set_assignments(assignments, size);
+    sim.fix_cells(source.mask.as_deref());
     for cell in &mut sim.cells {
         cell.dst_force = 0.3;
     }
@@ -30,7 +34,7 @@ pub fn init_image(sidelen: u32, source: Preset) -> (u32, Vec<SeedPos>, Vec<SeedC
 
 #[cfg(not(target_arch = "wasm32"))]
 pub fn init_canvas(
-    sidelen: u32,
+    size: (u32, u32),
     source: UnprocessedPreset,
 ) -> (u32, Vec<SeedPos>, Vec<SeedColor>, Sim) {
     use crate::app::calculate::drawing_process::DRAWING_CANVAS_SIZE;
@@ -38,16 +42,17 @@ pub fn init_canvas(
         image::ImageBuffer::from_vec(source.width, source.height, source.source_img).unwrap();
     let assignments = (0..(DRAWING_CANVAS_SIZE * DRAWING_CANVAS_SIZE)).collect::<Vec<usize>>();
 
-    let (seeds, colors, seeds_n) = init_colors(sidelen, imgpath);
-    let mut sim = Sim::new(source.name);
+    let grid = imgpath.dimensions();
+    let (seeds, colors, seeds_n) = init_colors(size, imgpath);
+    let mut sim = Sim::new(source.name, grid);
     sim.cells = vec![CellBody::new(0.0, 0.0, 0.0, 0.0, 0.0); seeds_n];
 
-    sim.set_assignments(assignments, sidelen);
+    sim.set_assignments(assignments, size);
     (seeds_n as u32, seeds, colors, sim)
 }
 
 fn init_colors(
-    sidelen: u32,
+    size: (u32, u32),
     source: ImageBuffer<image::Rgb<u8>, Vec<u8>>,
 ) -> (Vec<SeedPos>, Vec<SeedColor>, usize) {
     let mut seeds = Vec::new();
@@ -56,16 +61,14 @@ fn init_colors(
     let width = source.width() as usize;
     let height = source.height() as usize;
 
-    assert_eq!(width, height);
-
     let seeds_n = width * height;
-    let pixelsize = sidelen as f32 / width as f32;
+    let (cell_w, cell_h) = cell_size(size, (width, height));
 
-    for y in 0..width {
+    for y in 0..height {
         for x in 0..width {
             let p = source.get_pixel(x as u32, y as u32);
             seeds.push(SeedPos {
-                xy: [(x as f32 + 0.5) * pixelsize, (y as f32 + 0.5) * pixelsize],
+                xy: [(x as f32 + 0.5) * cell_w, (y as f32 + 0.5) * cell_h],
             });
             colors.push(SeedColor {
                 rgba: [
@@ -96,6 +99,16 @@ pub struct CellBody {
     dst_force: f32,
     age: u32,
     stroke_id: u32,
+    // outside the preset's mask, never moves but still pushes its neighbours
+    fixed: bool,
+}
+
+/// Pixels per cell across and down on a canvas of `size` pixels showing a grid of `grid` cells.
+fn cell_size(size: (u32, u32), grid: (usize, usize)) -> (f32, f32) {
+    (
+        size.0 as f32 / grid.0.max(1) as f32,
+        size.1 as f32 / grid.1.max(1) as f32,
+    )
 }
 
 const PERSONAL_SPACE: f32 = 0.95;
@@ -120,6 +133,7 @@ impl CellBody {
             accy: 0.0,
             age: 0,
             stroke_id: 0,
+            fixed: false,
         }
     }
     #[cfg(not(target_arch = "wasm32"))]
@@ -136,6 +150,12 @@ impl CellBody {
     }
 
     fn update(&mut self, pos: &mut SeedPos) {
+        if self.fixed {
+            self.accx = 0.0;
+            self.accy = 0.0;
+            return;
+        }
+
         self.velx += self.accx;
         self.vely += self.accy;
 
@@ -157,7 +177,7 @@ impl CellBody {
         self.age += 1;
     }
 
-    fn apply_dst_force(&mut self, pos: &SeedPos, sidelen: f32) {
+    fn apply_dst_force(&mut self, pos: &SeedPos, longest: f32) {
         let elapsed = self.age as f32 / 60.0;
         let factor = if self.dst_force == 0.0 {
             0.1
@@ -169,8 +189,8 @@ impl CellBody {
         let dy = self.dsty - pos.xy[1];
         let dist = (dx * dx + dy * dy).sqrt();
 
-        self.accx += (dx * dist * factor) / sidelen;
-        self.accy += (dy * dist * factor) / sidelen;
+        self.accx += (dx * dist * factor) / longest;
+        self.accy += (dy * dist * factor) / longest;
     }
 
     fn apply_neighbour_force(&mut self, pos: &SeedPos, other: &SeedPos, pixel_size: f32) -> f32 {
@@ -199,19 +219,19 @@ impl CellBody {
         weight.max(0.0)
     }
 
-    fn apply_wall_force(&mut self, pos: &SeedPos, sidelen: f32, pixel_size: f32) {
+    fn apply_wall_force(&mut self, pos: &SeedPos, (width, height): (f32, f32), pixel_size: f32) {
         let personal_space = pixel_size * PERSONAL_SPACE * 0.5;
 
         if pos.xy[0] < personal_space {
             self.accx += (personal_space - pos.xy[0]) / personal_space;
-        } else if pos.xy[0] > sidelen - personal_space {
-            self.accx -= (pos.xy[0] - (sidelen - personal_space)) / personal_space;
+        } else if pos.xy[0] > width - personal_space {
+            self.accx -= (pos.xy[0] - (width - personal_space)) / personal_space;
         }
 
         if pos.xy[1] < personal_space {
             self.accy += (personal_space - pos.xy[1]) / personal_space;
-        } else if pos.xy[1] > sidelen - personal_space {
-            self.accy -= (pos.xy[1] - (sidelen - personal_space)) / personal_space;
+        } else if pos.xy[1] > height - personal_space {
+            self.accy -= (pos.xy[1] - (height - personal_space)) / personal_space;
         }
     }
 
@@ -226,15 +246,18 @@ pub struct Sim {
     pub cells: Vec<CellBody>,
     name: String,
     reversed: bool,
+    // cells across and down
+    grid: (usize, usize),
 }
 
 impl Sim {
-    pub fn new(name: String) -> Self {
+    pub fn new(name: String, grid: (u32, u32)) -> Self {
         Self {
             cells: Vec::new(),
             //elapsed_frames: 0,
             name,
             reversed: false,
+            grid: (grid.0 as usize, grid.1 as usize),
         }
     }
 
@@ -255,48 +278,65 @@ impl Sim {
         self.reversed = !self.reversed;
     }
 
-    pub fn update(&mut self, positions: &mut [SeedPos], sidelen: u32) {
-        let grid_size = (self.cells.len() as f32).sqrt();
-        let pixel_size = sidelen as f32 / grid_size;
-        //dbg!(grid_size, pixel_size);
+    /// Cells across and down the grid.
+    pub fn grid(&self) -> (u32, u32) {
+        (self.grid.0 as u32, self.grid.1 as u32)
+    }
 
-        let mut grid = vec![vec![]; self.cells.len()];
+    /// One step on a canvas of `size` pixels.
+    pub fn update(&mut self, positions: &mut [SeedPos], size: (u32, u32)) {
+        let (grid_w, grid_h) = self.grid;
+        let (cell_w, cell_h) = cell_size(size, self.grid);
+        // cells are as good as square on a canvas shaped like the grid
+        let pixel_size = cell_w.min(cell_h);
+        let canvas = (size.0 as f32, size.1 as f32);
+        let longest = canvas.0.max(canvas.1);
+        //dbg!(grid_size, pixel_size);
 
+        // bucket the cells by the grid square they're over: `bucket_start[i]..bucket_start[i + 1]`
+        // of `bucketed` are the cells in square `i`. A flat counting sort instead of a `Vec` per
+        // square, so large grids don't allocate a million vectors every frame.
+        let square_of = |p: &SeedPos| {
+            let x = (p.xy[0] / cell_w).floor().clamp(0.0, grid_w as f32 - 1.0) as usize;
+            let y = (p.xy[1] / cell_h).floor().clamp(0.0, grid_h as f32 - 1.0) as usize;
+            y * grid_w + x
+        };
+        let mut bucket_start = vec![0; self.cells.len() + 1];
+        for p in positions.iter() {
+            bucket_start[square_of(p) + 1] += 1;
+        }
+        for i in 1..bucket_start.len() {
+            bucket_start[i] += bucket_start[i - 1];
+        }
+        let mut bucketed = vec![0; positions.len()];
+        let mut fill = bucket_start.clone();
         for (i, p) in positions.iter().enumerate() {
-            let x = p.xy[0] / pixel_size;
-            let y = p.xy[1] / pixel_size;
-
-            let index = (y.floor().clamp(0.0, grid_size - 1.0) * grid_size) as usize
-                + (x.floor().clamp(0.0, grid_size - 1.0) as usize);
-            //
-            grid[index].push(i);
+            let square = square_of(p);
+            bucketed[fill[square]] = i;
+            fill[square] += 1;
         }
 
         for (i, cell) in self.cells.iter_mut().enumerate() {
-            cell.apply_wall_force(&positions[i], sidelen as f32, pixel_size);
-            cell.apply_dst_force(&positions[i], sidelen as f32);
+            cell.apply_wall_force(&positions[i], canvas, pixel_size);
+            cell.apply_dst_force(&positions[i], longest);
         }
 
         for i in 0..self.cells.len() {
             let pos = positions[i].xy;
-            let col = (pos[0] / pixel_size) as usize;
-            let row = (pos[1] / pixel_size) as usize;
+            let col = (pos[0] / cell_w) as usize;
+            let row = (pos[1] / cell_h) as usize;
             let mut avg_xvel = 0.0;
             let mut avg_yvel = 0.0;
             let mut count = 0.0;
             for dy in 0..=2 {
                 for dx in 0..=2 {
-                    if col + dx == 0
-                        || row + dy == 0
-                        || col + dx >= grid_size as usize
-                        || row + dy >= grid_size as usize
-                    {
+                    if col + dx == 0 || row + dy == 0 || col + dx >= grid_w || row + dy >= grid_h {
                         continue;
                     }
                     let ncol = col + dx - 1;
                     let nrow = row + dy - 1;
-                    let nindex = nrow * (grid_size as usize) + ncol;
-                    for other in grid[nindex].iter() {
+                    let nindex = nrow * grid_w + ncol;
+                    for other in &bucketed[bucket_start[nindex]..bucket_start[nindex + 1]] {
                         if other == &i {
                             continue;
                         }
@@ -335,27 +375,39 @@ impl Sim {
         }
     }
 
-    pub fn set_assignments(&mut self, assignments: Vec<usize>, sidelen: u32) {
-        let width = (self.cells.len() as f32).sqrt();
-        let pixelsize = sidelen as f32 / width;
+    /// Sends each cell where `assignments` (target cell -> source cell) takes it, on a canvas of
+    /// `size` pixels.
+    pub fn set_assignments(&mut self, assignments: Vec<usize>, size: (u32, u32)) {
+        let width = self.grid.0;
+        let (cell_w, cell_h) = cell_size(size, self.grid);
 
         for (dst_idx, src_idx) in assignments.iter().enumerate() {
-            let src_x = (src_idx % width as usize) as f32;
-            let src_y = (src_idx / width as usize) as f32;
-            let dst_x = (dst_idx % width as usize) as f32;
-            let dst_y = (dst_idx / width as usize) as f32;
+            let src_x = (src_idx % width) as f32;
+            let src_y = (src_idx / width) as f32;
+            let dst_x = (dst_idx % width) as f32;
+            let dst_y = (dst_idx / width) as f32;
             let prev = self.cells[*src_idx];
 
             self.cells[*src_idx] = CellBody::new(
-                (src_x + 0.5) * pixelsize,
-                (src_y + 0.5) * pixelsize,
-                (dst_x + 0.5) * pixelsize,
-                (dst_y + 0.5) * pixelsize,
+                (src_x + 0.5) * cell_w,
+                (src_y + 0.5) * cell_h,
+                (dst_x + 0.5) * cell_w,
+                (dst_y + 0.5) * cell_h,
                 prev.dst_force,
             );
 
             self.cells[*src_idx].age = prev.age;
             self.cells[*src_idx].stroke_id = prev.stroke_id;
+            self.cells[*src_idx].fixed = prev.fixed;
+        }
+    }
+
+    /// Keeps the cells outside `mask` where they are, see `Preset::mask`. A mask for another
+    /// grid is ignored.
+    pub fn fix_cells(&mut self, mask: Option<&[bool]>) {
+        let mask = mask.filter(|mask| mask.len() == self.cells.len());
+        for (i, cell) in self.cells.iter_mut().enumerate() {
+            cell.fixed = mask.is_some_and(|mask| !mask[i]);
         }
     }
 
@@ -0,0 +1,162 @@
+use egui::{Color32, Rect, TextureHandle};
+
+use crate::app::calculate::metrics::cell_costs;
+use crate::app::preset::Preset;
+
+// roughly this many flow arrows along the longer side, whatever the resolution
+const ARROWS_PER_SIDE: usize = 24;
+const HEATMAP_ALPHA: f32 = 0.7;
+
+/// What gets drawn over the canvas to show how the shown preset's cells were assigned.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum AnalysisView {
+    #[default]
+    None,
+    /// What each cell pays for where it ended up, only known for morphs made since the app
+    /// started.
+    Cost,
+    /// How far each cell travels.
+    Displacement,
+    /// Arrows from where a sample of cells start to where they end up.
+    Flow,
+}
+
+impl AnalysisView {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AnalysisView::None => "None",
+            AnalysisView::Cost => "Cost",
+            AnalysisView::Displacement => "Displacement",
+            AnalysisView::Flow => "Flow",
+        }
+    }
+}
+
+/// The selected `AnalysisView` and the heatmap drawn for it last.
+#[derive(Default)]
+pub struct AnalysisOverlay {
+    pub view: AnalysisView,
+    // view and preset index the heatmap was made for
+    heatmap: Option<(AnalysisView, usize, TextureHandle)>,
+}
+
+impl AnalysisOverlay {
+    /// Drops the cached heatmap, for when a preset changes under the same index.
+    pub fn invalidate(&mut self) {
+        self.heatmap = None;
+    }
+
+    /// Draws the selected view of `presets[index]` over the canvas at `rect`.
+    pub fn paint(&mut self, ui: &egui::Ui, rect: Rect, presets: &[Preset], index: usize) {
+        let Some(preset) = presets.get(index) else {
+            return;
+        };
+        let painter = ui.painter_at(rect);
+        match self.view {
+            AnalysisView::None => {}
+            AnalysisView::Flow => paint_flow(&painter, rect, preset),
+            view @ (AnalysisView::Cost | AnalysisView::Displacement) => {
+                if !matches!(&self.heatmap, Some((v, i, _)) if *v == view && *i == index) {
+                    self.heatmap = heatmap(ui.ctx(), view, preset).map(|tex| (view, index, tex));
+                }
+                if let Some((_, _, tex)) = &self.heatmap {
+                    let uv = Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                    painter.image(tex.id(), rect, uv, Color32::WHITE);
+                }
+            }
+        }
+    }
+}
+
+/// Cells across and down the preset's grid.
+fn grid(preset: &Preset) -> (usize, usize) {
+    (preset.inner.width as usize, preset.inner.height as usize)
+}
+
+fn displacement(target: usize, source: usize, width: usize) -> f32 {
+    let dx = (target % width) as f32 - (source % width) as f32;
+    let dy = (target / width) as f32 - (source / width) as f32;
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// One texel per cell, `None` if the preset has nothing to show for `view`.
+fn heatmap(ctx: &egui::Context, view: AnalysisView, preset: &Preset) -> Option<TextureHandle> {
+    let (width, height) = grid(preset);
+    let values: Vec<f32> = match view {
+        AnalysisView::Cost => {
+            let source = preset
+                .inner
+                .source_img
+                .chunks_exact(3)
+                .map(|p| (p[0], p[1], p[2]))
+                .collect::<Vec<_>>();
+            cell_costs(preset.settings.as_deref()?, &source, &preset.assignments)
+                .ok()?
+                .into_iter()
+                .map(|c| c as f32)
+                .collect()
+        }
+        AnalysisView::Displacement => preset
+            .assignments
+            .iter()
+            .enumerate()
+            .map(|(target, &source)| displacement(target, source, width))
+            .collect(),
+        _ => return None,
+    };
+    if values.len() != width * height || values.is_empty() {
+        return None;
+    }
+
+    // a handful of outliers would otherwise wash out everything else
+    let mut sorted = values.clone();
+    sorted.sort_by(f32::total_cmp);
+    let max = sorted[(sorted.len() - 1) * 99 / 100].max(f32::EPSILON);
+    let pixels = values
+        .iter()
+        .map(|&v| heat_color((v / max).clamp(0.0, 1.0)))
+        .collect();
+    let image = egui::ColorImage::new([width, height], pixels);
+    Some(ctx.load_texture("analysis heatmap", image, egui::TextureOptions::NEAREST))
+}
+
+/// Dark blue through red to yellow as `t` goes from 0 to 1.
+fn heat_color(t: f32) -> Color32 {
+    const STOPS: [[f32; 3]; 4] = [
+        [20.0, 20.0, 120.0],
+        [160.0, 30.0, 140.0],
+        [240.0, 80.0, 30.0],
+        [255.0, 240.0, 80.0],
+    ];
+    let scaled = t * (STOPS.len() - 1) as f32;
+    let i = (scaled as usize).min(STOPS.len() - 2);
+    let f = scaled - i as f32;
+    let [r, g, b] = std::array::from_fn(|c| STOPS[i][c] + (STOPS[i + 1][c] - STOPS[i][c]) * f);
+    Color32::from_rgba_unmultiplied(r as u8, g as u8, b as u8, (HEATMAP_ALPHA * 255.0) as u8)
+}
+
+/// Arrows from source to destination for every `step`th cell on both axes.
+fn paint_flow(painter: &egui::Painter, rect: Rect, preset: &Preset) {
+    let (width, height) = grid(preset);
+    if preset.assignments.len() != width * height || width == 0 || height == 0 {
+        return;
+    }
+    let step = (width.max(height) / ARROWS_PER_SIDE).max(1);
+    let cell = rect.size() / egui::vec2(width as f32, height as f32);
+    let center = |index: usize| {
+        let (x, y) = ((index % width) as f32 + 0.5, (index / width) as f32 + 0.5);
+        rect.min + egui::vec2(x * cell.x, y * cell.y)
+    };
+    let stroke = egui::Stroke::new(1.5, Color32::from_rgba_unmultiplied(255, 255, 255, 200));
+    for y in (step / 2..height).step_by(step) {
+        for x in (step / 2..width).step_by(step) {
+            let target = y * width + x;
+            let source = preset.assignments[target];
+            if source == target {
+                continue;
+            }
+            let from = center(source);
+            painter.arrow(from, center(target) - from, stroke);
+        }
+    }
+}
@@ -1,9 +1,26 @@
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app::calculate::metrics::Metrics;
+use crate::app::calculate::util::GenerationSettings;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Preset {
+    /// Stays with the preset while others are added or removed around it, unlike its index.
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
     pub inner: UnprocessedPreset,
     pub assignments: Vec<usize>,
+    /// How good the assignment is, `None` for the bundled presets.
+    #[serde(default)]
+    pub metrics: Option<Metrics>,
+    /// Cells that morph, the others stay put. `None` when they all do.
+    #[serde(default)]
+    pub mask: Option<Vec<bool>>,
+    /// What the morph was made with, for the cost view. Not saved: the per-cell costs are
+    /// cheap to work out again, the custom images in here are not cheap to store.
+    #[serde(skip)]
+    pub settings: Option<Box<GenerationSettings>>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]